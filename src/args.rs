@@ -1,19 +1,78 @@
-use chrono::{Duration, Utc};
-use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
-use crate::database::TodoData;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use clap::{error::ErrorKind, ArgGroup, Args, CommandFactory, Parser, Subcommand};
+
+use crate::{
+    database::{
+        extract_inline_tags, ArchiveFilter, Priority, ReminderTime, SortKey, SubtaskAction,
+        TaskChanges, TaskFilter, TaskSort, TaskStatus, TodoData,
+    },
+    dates::{parse_age, parse_date, parse_datetime, parse_duration},
+    ids::{expand_ids, parse_id_range, IdRange},
+    recurrence::{parse_recurrence, Recurrence},
+    views::OutputFormat,
+};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct Cli {
+    /// Path to the database file (defaults to the XDG data directory)
+    #[arg(long, global = true, env = "TODO_DB")]
+    pub db: Option<PathBuf>,
+
+    /// Output format for listings and stats
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Show when tasks were created, last updated and completed in tables
+    #[arg(long, global = true, default_value_t = false)]
+    pub timestamps: bool,
+
     #[command(subcommand)]
     pub options: OptionTypes,
 }
 
+impl Cli {
+    /// Checks the combinations of arguments clap's groups can't express
+    ///
+    /// # Errors
+    ///
+    /// Returns a usage error for `task update --project <PROJECT>` with nothing to change
+    pub fn check(&self) -> Result<(), clap::Error> {
+        if let OptionTypes::Task(TaskOptions {
+            command: TaskSubcommand::Update(update),
+        }) = &self.options
+        {
+            if update.project_is_filter() && !update.has_changes() {
+                return Err(Self::command().error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--project picks the tasks to change when no ids or filters are given, \
+                    add a change such as --complete",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum OptionTypes {
     /// Add task, update task, view task
     Task(TaskOptions),
+
+    /// Deliver reminders that are due
+    Remind(RemindOptions),
+
+    /// Show recent changes across all tasks, newest first
+    Log(LogOptions),
+
+    /// Undo the most recent adds, updates, deletes and archives
+    Undo(UndoOptions),
+
+    /// Manage archived tasks
+    Archive(ArchiveOptions),
 }
 
 #[derive(Debug, Args)]
@@ -33,13 +92,38 @@ pub enum TaskSubcommand {
     /// View tasks
     View(ViewTask),
 
-    /// Archive a task
+    /// Archive a task, or every task matching the filters
     Archive(ArchiveTask),
 
+    /// Move an archived task back to the task list
+    Unarchive(UnarchiveTask),
+
     /// View archived tasks
     ViewArchive(ViewArchiveTask),
 
+    /// Count pending or overdue tasks
     Stats(GetStats),
+
+    /// Search task descriptions, notes and projects, best matches first
+    Search(SearchTask),
+
+    /// List, pause, resume or end recurring series
+    Repeat(RepeatOptions),
+
+    /// Make a task wait for other tasks to be completed
+    Depend(DependTask),
+
+    /// Set a reminder for a task
+    Remind(RemindTask),
+
+    /// Show everything that happened to a task, oldest first
+    History(HistoryTask),
+
+    /// Edit the notes of a task in $EDITOR, or set them from stdin
+    Note(NoteTask),
+
+    /// Show a task in full, notes included
+    Show(ShowTask),
 }
 
 #[derive(Debug, Args)]
@@ -52,71 +136,410 @@ pub struct AddTask {
     /// Task description
     pub task: String,
 
-    #[arg(short, long, default_value_t = format!("{}", (Utc::now() + Duration::days(7)).format("%Y-%m-%d")))]
-    /// Due date in format 'YYYY-MM-DD'
-    pub due_date: String,
+    #[arg(short, long, value_parser = parse_date, default_value = "+1w")]
+    /// Due date as 'YYYY-MM-DD' or an expression like 'tomorrow', '+3d', 'fri' or 'eow'
+    pub due_date: NaiveDate,
 
     /// Status
     #[arg(short, long, default_value_t = false)]
     pub complete: bool,
+
+    /// Priority (low, medium, high, urgent or 1-4)
+    #[arg(long, value_enum, default_value_t = Priority::Medium)]
+    pub priority: Priority,
+
+    /// Tag the task, repeat or separate with commas for several tags.
+    /// Words like +tag in the description are added as tags too
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Repeat the task when it is completed: daily, weekly, monthly, weekdays or an
+    /// interval like 'every 2w'
+    #[arg(long, value_parser = parse_recurrence)]
+    pub repeat: Option<Recurrence>,
+
+    /// Add the task as a subtask of this task
+    #[arg(long)]
+    pub parent: Option<u64>,
 }
 
 impl AddTask {
     #[must_use]
     pub fn to_todo_data(self) -> TodoData {
+        let (task, mut tags) = extract_inline_tags(&self.task);
+        tags.extend(self.tags);
+
         TodoData {
             project: self.project,
-            task: self.task,
+            task,
             due_date: self.due_date,
             complete: self.complete,
+            priority: self.priority,
+            tags,
+            repeat: self.repeat,
+            parent_id: self.parent,
         }
     }
 }
 
-#[derive(Debug, Args, Copy, Clone)]
+#[derive(Debug, Args)]
+#[allow(clippy::struct_excessive_bools)]
+#[command(group(
+    ArgGroup::new("changes")
+        .required(true)
+        .multiple(true)
+        .args(["task", "project", "due_date", "complete", "incomplete", "priority", "delete"]),
+))]
+#[command(group(
+    ArgGroup::new("target")
+        .required(true)
+        .multiple(true)
+        .args(["ids", "in_project", "project", "tags", "status", "overdue"]),
+))]
 pub struct UpdateTask {
-    /// Row ID for task
-    pub id: u64,
+    /// Row IDs of the tasks, separated by commas and with ranges e.g. '3,5,9-14'
+    #[arg(
+        value_delimiter = ',',
+        value_parser = parse_id_range,
+        conflicts_with_all = ["in_project", "tags", "status", "overdue"],
+    )]
+    pub ids: Vec<IdRange>,
+
+    /// Change every task in this project instead, glob patterns like 'Sprint*' match several
+    #[arg(long)]
+    pub in_project: Option<String>,
+
+    /// Change every task with these tags instead, repeat or separate with commas to require
+    /// several
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Change every pending or done task instead
+    #[arg(long, value_enum)]
+    pub status: Option<TaskStatus>,
+
+    /// Change every pending task past its due date instead
+    #[arg(long, default_value_t = false)]
+    pub overdue: bool,
+
+    /// New task description
+    #[arg(short, long)]
+    pub task: Option<String>,
+
+    /// Move the task to another project. Without ids or other filters, change every task in
+    /// this project instead
+    #[arg(short, long)]
+    pub project: Option<String>,
+
+    /// New due date as 'YYYY-MM-DD' or an expression like 'tomorrow', '+3d', 'fri' or 'eow'
+    #[arg(long, value_parser = parse_date)]
+    pub due_date: Option<NaiveDate>,
 
     /// Mark as complete (no args needed just the flag i.e -c or --complete)
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, conflicts_with = "incomplete")]
     pub complete: bool,
 
-    /// Delete task (no args needed just the flag i.e -d or --delete)
+    /// Mark as not complete (no args needed just the flag i.e -i or --incomplete)
     #[arg(short, long, default_value_t = false)]
+    pub incomplete: bool,
+
+    /// New priority (low, medium, high, urgent or 1-4)
+    #[arg(long, value_enum)]
+    pub priority: Option<Priority>,
+
+    /// Delete task (no args needed just the flag i.e -d or --delete)
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["task", "project", "due_date", "complete", "incomplete", "priority"],
+    )]
     pub delete: bool,
+
+    /// Don't ask for confirmation before deleting, or changing several tasks
+    #[arg(short, long, default_value_t = false)]
+    pub yes: bool,
+
+    /// What to do with subtasks when deleting a task that has them
+    #[arg(long, value_enum, default_value_t = SubtaskAction::Refuse, requires = "delete")]
+    pub subtasks: SubtaskAction,
 }
 
 impl UpdateTask {
+    /// The tasks given by id, in the order given
     #[must_use]
-    pub fn to_todo_data(self) -> TodoData {
-        TodoData {
-            project: String::from("Placeholder"),
-            task: String::from("Placeholder"),
-            due_date: String::from("Placeholder"),
-            complete: self.complete,
+    pub fn task_ids(&self) -> Vec<u64> {
+        expand_ids(&self.ids)
+    }
+
+    /// Whether `--project` picks the tasks to change rather than moving them, which it does
+    /// when nothing else picks them
+    #[must_use]
+    pub const fn project_is_filter(&self) -> bool {
+        self.project.is_some()
+            && self.ids.is_empty()
+            && self.in_project.is_none()
+            && self.tags.is_empty()
+            && self.status.is_none()
+            && !self.overdue
+    }
+
+    /// Whether anything is to be changed besides a `--project` that picks the tasks
+    #[must_use]
+    pub const fn has_changes(&self) -> bool {
+        self.task.is_some()
+            || (self.project.is_some() && !self.project_is_filter())
+            || self.due_date.is_some()
+            || self.complete
+            || self.incomplete
+            || self.priority.is_some()
+            || self.delete
+    }
+
+    /// Filter selecting the tasks to change when no ids are given
+    #[must_use]
+    pub fn to_task_filter(&self) -> TaskFilter {
+        let project = if self.project_is_filter() {
+            self.project.clone()
+        } else {
+            self.in_project.clone()
+        };
+
+        TaskFilter {
+            project,
+            tags: self.tags.clone(),
+            status: self.status.unwrap_or_default(),
+            overdue: self.overdue,
+            ..TaskFilter::default()
+        }
+    }
+
+    #[must_use]
+    pub fn to_task_changes(self) -> TaskChanges {
+        let complete = if self.complete {
+            Some(true)
+        } else if self.incomplete {
+            Some(false)
+        } else {
+            None
+        };
+
+        TaskChanges {
+            project: if self.project_is_filter() {
+                None
+            } else {
+                self.project
+            },
+            task: self.task,
+            due_date: self.due_date,
+            complete,
+            priority: self.priority,
+            notes: None,
         }
     }
 }
 
 #[derive(Debug, Args)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ViewTask {
-    /// View specific project
-    #[arg(short, long, default_value_t = String::from("All"))]
-    pub project: String,
+    /// View specific project, glob patterns like 'Work*' match several projects
+    #[arg(short, long)]
+    pub project: Option<String>,
+
+    /// Only show tasks with these tags, repeat or separate with commas to require several
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Show tasks with any of the given tags instead of all of them
+    #[arg(long, default_value_t = false, requires = "tags")]
+    pub any_tag: bool,
+
+    /// Only show pending or done tasks
+    #[arg(short, long, value_enum, default_value_t = TaskStatus::All)]
+    pub status: TaskStatus,
+
+    /// Only show pending tasks past their due date
+    #[arg(short, long, default_value_t = false)]
+    pub overdue: bool,
+
+    /// Only show tasks due before this date
+    #[arg(long, value_parser = parse_date)]
+    pub due_before: Option<NaiveDate>,
+
+    /// Only show tasks due after this date
+    #[arg(long, value_parser = parse_date)]
+    pub due_after: Option<NaiveDate>,
+
+    /// Only show tasks due today
+    #[arg(long, default_value_t = false)]
+    pub due_today: bool,
+
+    /// Only show tasks whose description contains this text
+    #[arg(long)]
+    pub search: Option<String>,
+
+    /// Only show pending tasks that aren't waiting on other tasks
+    #[arg(long, default_value_t = false)]
+    pub ready: bool,
+
+    /// Only show tasks added before this date
+    #[arg(long, value_parser = parse_date)]
+    pub created_before: Option<NaiveDate>,
+
+    /// Only show tasks added after this date
+    #[arg(long, value_parser = parse_date)]
+    pub created_after: Option<NaiveDate>,
+
+    /// Only show tasks completed before this date
+    #[arg(long, value_parser = parse_date)]
+    pub completed_before: Option<NaiveDate>,
+
+    /// Only show tasks completed after this date
+    #[arg(long, value_parser = parse_date)]
+    pub completed_after: Option<NaiveDate>,
+
+    /// Sort by these keys in order, separated by commas e.g. 'project,due'
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        env = "TODO_SORT",
+        default_value = "priority,due"
+    )]
+    pub sort: Vec<SortKey>,
+
+    /// Reverse the sort order
+    #[arg(short, long, default_value_t = false)]
+    pub reverse: bool,
+}
+
+impl ViewTask {
+    #[must_use]
+    pub fn to_task_filter(self) -> TaskFilter {
+        TaskFilter {
+            project: self.project,
+            tags: self.tags,
+            any_tag: self.any_tag,
+            status: self.status,
+            overdue: self.overdue,
+            due_before: self.due_before,
+            due_after: self.due_after,
+            due_today: self.due_today,
+            search: self.search,
+            ready: self.ready,
+            created_before: self.created_before,
+            created_after: self.created_after,
+            completed_before: self.completed_before,
+            completed_after: self.completed_after,
+        }
+    }
+
+    #[must_use]
+    pub fn to_task_sort(&self) -> TaskSort {
+        TaskSort {
+            keys: self.sort.clone(),
+            reverse: self.reverse,
+        }
+    }
 }
 
 #[derive(Debug, Args)]
+#[command(group(
+    ArgGroup::new("target")
+        .required(true)
+        .multiple(true)
+        .args(["ids", "completed", "older_than", "project"]),
+))]
 pub struct ArchiveTask {
-    /// Row ID for task to archive
+    /// Row IDs of the tasks to archive, separated by commas and with ranges e.g. '3,5,9-14'
+    #[arg(
+        value_delimiter = ',',
+        value_parser = parse_id_range,
+        conflicts_with_all = ["completed", "older_than", "project"],
+    )]
+    pub ids: Vec<IdRange>,
+
+    /// Archive every completed task
+    #[arg(short, long, default_value_t = false)]
+    pub completed: bool,
+
+    /// Archive every task added longer ago than this, e.g. '30d', '6m' or '1y'
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: Option<NaiveDate>,
+
+    /// Archive every task in this project, glob patterns like 'Work*' match several projects
+    #[arg(short, long)]
+    pub project: Option<String>,
+
+    /// What to do with subtasks when archiving a task that has them
+    #[arg(long, value_enum, default_value_t = SubtaskAction::Refuse)]
+    pub subtasks: SubtaskAction,
+
+    /// Purge tasks archived longer ago than this whenever tasks are archived, e.g. '1y'
+    #[arg(long, env = "TODO_ARCHIVE_RETENTION", value_parser = parse_age)]
+    pub retention: Option<NaiveDate>,
+
+    /// Don't ask for confirmation before archiving several tasks
+    #[arg(short, long, default_value_t = false)]
+    pub yes: bool,
+}
+
+impl ArchiveTask {
+    /// The tasks given by id, in the order given
+    #[must_use]
+    pub fn task_ids(&self) -> Vec<u64> {
+        expand_ids(&self.ids)
+    }
+
+    /// Filter selecting the tasks to archive in bulk, all filters given must match
+    #[must_use]
+    pub fn to_task_filter(&self) -> TaskFilter {
+        let status = if self.completed {
+            TaskStatus::Done
+        } else {
+            TaskStatus::All
+        };
+
+        TaskFilter {
+            project: self.project.clone(),
+            status,
+            created_before: self.older_than,
+            ..TaskFilter::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct UnarchiveTask {
+    /// Row ID of the archived task, the same as before it was archived
     pub id: u64,
 }
 
 #[derive(Debug, Args)]
 pub struct ViewArchiveTask {
-    /// View archived tasks for specific project
-    #[arg(short, long, default_value_t = String::from("All"))]
-    pub project: String,
+    /// View archived tasks for specific project, glob patterns like 'Work*' match several
+    /// projects
+    #[arg(short, long)]
+    pub project: Option<String>,
+
+    /// Only show tasks archived before this date
+    #[arg(long, value_parser = parse_date)]
+    pub archived_before: Option<NaiveDate>,
+
+    /// Only show tasks archived after this date
+    #[arg(long, value_parser = parse_date)]
+    pub archived_after: Option<NaiveDate>,
+}
+
+impl ViewArchiveTask {
+    #[must_use]
+    pub fn to_archive_filter(&self) -> ArchiveFilter {
+        ArchiveFilter {
+            project: self.project.clone(),
+            archived_before: self.archived_before,
+            archived_after: self.archived_after,
+        }
+    }
 }
 
 #[derive(Debug, Args)]
@@ -128,4 +551,213 @@ pub struct GetStats {
     /// Get overdue tasks count (no args needed just the flag i.e -o or -overdue)
     #[arg(short, long, default_value_t = false)]
     pub overdue: bool,
+
+    /// Get the average number of days between adding and completing a task
+    #[arg(short, long, default_value_t = false)]
+    pub avg_completion: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchTask {
+    /// Words to search for, "quoted phrases" must appear together and a trailing * matches
+    /// the start of a word e.g. 'rep*'
+    #[arg(required = true, num_args = 1..)]
+    pub query: Vec<String>,
+
+    /// Include archived tasks in the results
+    #[arg(short, long, default_value_t = false)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DependTask {
+    /// Row ID for the task that has to wait
+    pub id: u64,
+
+    /// Tasks that must be completed first, repeat or separate with commas for several
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub on: Vec<u64>,
+
+    /// Remove the dependencies instead of adding them
+    #[arg(short, long, default_value_t = false)]
+    pub remove: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("time").required(true).args(["at", "before"])))]
+pub struct RemindTask {
+    /// Row ID for task
+    pub id: u64,
+
+    /// When to remind you, e.g. '2024-06-14 17:30', 'tomorrow 8:00', 'fri' or '+2h'.
+    /// Dates without a time mean 09:00
+    #[arg(long, value_parser = parse_datetime)]
+    pub at: Option<NaiveDateTime>,
+
+    /// How long before the due date (at 09:00) to remind you, e.g. '1d', '2h' or '30min'
+    #[arg(long, value_parser = parse_duration)]
+    pub before: Option<TimeDelta>,
+}
+
+impl RemindTask {
+    #[must_use]
+    pub fn to_reminder_time(&self) -> ReminderTime {
+        match (self.at, self.before) {
+            (Some(at), _) => ReminderTime::At(at),
+            (None, before) => ReminderTime::BeforeDue(before.unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryTask {
+    /// Row ID for task, tasks that were deleted or archived keep their history
+    pub id: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct NoteTask {
+    /// Row ID for task
+    pub id: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct ShowTask {
+    /// Row ID for task, archived tasks are shown too
+    pub id: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct LogOptions {
+    /// Number of changes to show
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub limit: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct UndoOptions {
+    /// Number of commands to undo, most recent first
+    #[arg(short, long, default_value_t = 1)]
+    pub steps: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveOptions {
+    #[command(subcommand)]
+    pub command: ArchiveSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ArchiveSubcommand {
+    /// Permanently delete tasks archived a while ago
+    Purge(PurgeArchive),
+}
+
+#[derive(Debug, Args)]
+pub struct PurgeArchive {
+    /// Purge tasks archived longer ago than this, e.g. '30d', '6m' or '1y'
+    #[arg(long, value_parser = parse_age)]
+    pub older_than: NaiveDate,
+
+    /// List the tasks that would be purged without deleting them
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Save the purged tasks to this JSON file before deleting them
+    #[arg(long)]
+    pub export: Option<PathBuf>,
+}
+
+impl PurgeArchive {
+    #[must_use]
+    pub fn to_archive_filter(&self) -> ArchiveFilter {
+        ArchiveFilter {
+            archived_before: Some(self.older_than),
+            ..ArchiveFilter::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RemindOptions {
+    #[command(subcommand)]
+    pub command: RemindSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RemindSubcommand {
+    /// List reminders that are due and mark them delivered, meant to run from cron or a
+    /// systemd timer
+    Check(CheckReminders),
+}
+
+#[derive(Debug, Args)]
+pub struct CheckReminders {
+    /// Shell command to run for each reminder, it gets the task as environment variables and
+    /// as JSON on stdin
+    #[arg(long, env = "TODO_NOTIFY_CMD")]
+    pub notify: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RepeatOptions {
+    #[command(subcommand)]
+    pub command: RepeatSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RepeatSubcommand {
+    /// List recurring series with their latest occurrence
+    List,
+
+    /// Stop adding occurrences until the series is resumed
+    Pause(SeriesId),
+
+    /// Resume a paused series
+    Resume(SeriesId),
+
+    /// End a series, its tasks are kept but no longer repeat
+    End(SeriesId),
+}
+
+#[derive(Debug, Args)]
+pub struct SeriesId {
+    /// Series ID as shown by 'task repeat list'
+    pub id: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(args: &[&str]) -> Result<UpdateTask, clap::Error> {
+        let cli = Cli::try_parse_from(["todo", "task", "update"].iter().chain(args))?;
+        cli.check()?;
+
+        match cli.options {
+            OptionTypes::Task(TaskOptions {
+                command: TaskSubcommand::Update(update),
+            }) => Ok(update),
+            _ => unreachable!("parsed a task update"),
+        }
+    }
+
+    #[test]
+    fn project_picks_tasks_when_nothing_else_does() {
+        let by_project = update(&["--project", "Sprint12", "--complete"]).unwrap();
+        assert_eq!(
+            Some("Sprint12"),
+            by_project.to_task_filter().project.as_deref()
+        );
+        assert_eq!(None, by_project.to_task_changes().project);
+
+        let moved = update(&["--in-project", "Sprint12", "--project", "Sprint13"]).unwrap();
+        assert_eq!(Some("Sprint12"), moved.to_task_filter().project.as_deref());
+        assert_eq!(Some("Sprint13"), moved.to_task_changes().project.as_deref());
+
+        let single = update(&["3", "--project", "Home"]).unwrap();
+        assert_eq!(Some("Home"), single.to_task_changes().project.as_deref());
+
+        assert!(update(&["--project", "Sprint12"]).is_err());
+    }
 }
@@ -1,125 +1,737 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
 use crate::{
-    args::TaskSubcommand,
+    args::{
+        ArchiveSubcommand, ArchiveTask, GetStats, LogOptions, RemindSubcommand, RepeatSubcommand,
+        TaskSubcommand, UndoOptions, UpdateTask,
+    },
     database::{
-        archive_task, count_overdue, count_pending, get_all_archived_tasks, get_all_tasks,
-        get_archived_tasks, get_tasks,
+        add_dependencies, archive_task, archive_tasks, average_days_to_complete, count_overdue,
+        count_pending, delete_task, delete_tasks, due_reminders, end_series, get_archived_task,
+        get_archived_tasks, get_series, get_task, get_tasks_by_id,
+        history::{recent_events, task_history},
+        mark_reminder_delivered, purge_archived_tasks, query_tasks, remove_dependencies,
+        search_tasks, set_reminder, set_series_paused, unarchive_task,
+        undo::undo,
+        update_task, update_tasks, ArchiveFilter, SortKey, SubtaskAction, TaskChanges, TaskSort,
+        TodoView,
     },
-    views::show_data,
+    editor::read_text,
+    error::{Result, TodoError},
+    notify::run_notify_command,
+    views::{event_changes, tasks_to_json, Output, OutputFormat, StatValue},
 };
 
 static DB_FILE: &str = "todo.db";
 
+/// Resolves the database location
+///
+/// An explicit path (from `--db` or `TODO_DB`) wins, otherwise the database lives in
+/// `$XDG_DATA_HOME/todo/todo.db`. A database left next to the executable by older
+/// versions is moved to the default location the first time it is used.
+///
+/// # Errors
+///
+/// Returns an error if the data directory cannot be created or the old database cannot be moved
+pub fn resolve_db_path(db: Option<PathBuf>) -> io::Result<PathBuf> {
+    if let Some(path) = db {
+        return Ok(path);
+    }
+
+    let Some(path) = default_db_path(env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) else {
+        // No home directory to speak of, keep using the database next to the binary
+        return legacy_db_path();
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let legacy = legacy_db_path()?;
+    if legacy.is_file() && !path.exists() {
+        move_file(&legacy, &path)?;
+        eprintln!(
+            "Moved database from {} to {}",
+            legacy.display(),
+            path.display()
+        );
+    }
+
+    Ok(path)
+}
+
+/// Builds the default database path following the XDG base directory specification
+fn default_db_path(xdg_data_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let data_dir = xdg_data_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            home.filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+
+    Some(data_dir.join("todo").join(DB_FILE))
+}
+
+/// Location of the database used by versions before the path was configurable
+fn legacy_db_path() -> io::Result<PathBuf> {
+    let path = env::current_exe()?;
+    let dir = path.parent().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no parent directory", path.display()),
+        )
+    })?;
+
+    Ok(dir.join(DB_FILE))
+}
+
+/// Moves a file, falling back to copy and delete when crossing filesystems
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// Asks a yes/no question and reads the answer from `input`, usually the terminal
+///
+/// Anything but an explicit yes counts as no.
+fn confirm(question: &str, input: &mut dyn BufRead) -> io::Result<bool> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The database path as the `&str` the database layer takes
+fn db_file(db_path: &Path) -> io::Result<&str> {
+    db_path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "database path must be valid UTF-8",
+        )
+    })
+}
+
 /// Handles task subcommands by processing the data and interacting with the database
 ///
-/// # Panics
+/// `timestamps` adds the task timestamps to tables.
 ///
-/// This function will panic if:
-/// - The current executable path cannot be determined
-/// - Database file path conversion fails
-/// - Database operations fail unexpectedly
-#[allow(clippy::too_many_lines)]
-pub fn handle_data(data_to_handle: TaskSubcommand) {
-    let path = std::env::current_exe().unwrap();
-    let dir = path.parent().expect("Binary should be in a directory");
+/// # Errors
+///
+/// Returns an error if the database path is not valid UTF-8 or if any database operation fails
+pub fn handle_data(
+    data_to_handle: TaskSubcommand,
+    db_path: &Path,
+    format: OutputFormat,
+    timestamps: bool,
+) -> Result<()> {
+    let db_file = db_file(db_path)?;
+    let output = format.output(timestamps);
 
     match data_to_handle {
         TaskSubcommand::Add(task) => {
             let new_task = task.to_todo_data();
-            new_task
-                .write_data(dir.join(DB_FILE).to_str().unwrap())
-                .expect("No data");
+            new_task.write_data(db_file)?;
         }
         TaskSubcommand::Update(task) => {
-            let parameters = task;
-            let new_task = task.to_todo_data();
-            new_task
-                .update_task(parameters, dir.join(DB_FILE).to_str().unwrap())
-                .expect("Database does not exist, create task first");
+            handle_update(task, db_file, &*output, &mut io::stdin().lock())?;
         }
         TaskSubcommand::View(view) => {
-            if &view.project[..] == "All" {
-                let results = get_all_tasks(dir.join(DB_FILE).to_str().unwrap());
-                match results {
-                    Ok(data) => {
-                        let output = show_data(data);
-                        output.printstd();
-                    }
-                    Err(_) => eprintln!("No database or data"),
-                }
+            let sort = view.to_task_sort();
+            let data = query_tasks(&view.to_task_filter(), &sort, db_file)?;
+
+            output.print_tasks(data);
+        }
+        TaskSubcommand::Archive(archive) => {
+            handle_archive(&archive, db_file, &*output, &mut io::stdin().lock())?;
+        }
+        TaskSubcommand::Unarchive(unarchive) => {
+            unarchive_task(unarchive.id, db_file)?;
+            println!("Task {} restored from the archive", unarchive.id);
+        }
+        TaskSubcommand::ViewArchive(view_archive) => {
+            let data = get_archived_tasks(&view_archive.to_archive_filter(), db_file)?;
+            let (heading, empty_message) = view_archive.project.as_ref().map_or_else(
+                || {
+                    (
+                        String::from("\n=== ARCHIVED TASKS ==="),
+                        String::from("No archived tasks found"),
+                    )
+                },
+                |project| {
+                    (
+                        format!("\n=== ARCHIVED TASKS: {project} ==="),
+                        format!("No archived tasks found for project: {project}"),
+                    )
+                },
+            );
+
+            // Machine readable formats still print an empty listing
+            if data.is_empty() && format == OutputFormat::Table {
+                output.print_notice(&empty_message);
             } else {
-                let results = get_tasks(&view.project[..], dir.join(DB_FILE).to_str().unwrap());
-                match results {
-                    Ok(data) => {
-                        let output = show_data(data);
-                        output.printstd();
-                    }
-                    Err(_) => eprintln!("No database or data"),
+                output.print_notice(&heading);
+                output.print_tasks(data);
+            }
+        }
+        TaskSubcommand::Stats(numbers) => handle_stats(&numbers, db_file, &*output)?,
+        TaskSubcommand::Search(search) => {
+            let data = search_tasks(&search.query.join(" "), search.archived, db_file)?;
+
+            // Tables list archived matches separately, other formats mark each task instead
+            if format == OutputFormat::Table {
+                let (archived, live): (Vec<_>, Vec<_>) =
+                    data.into_iter().partition(|task| task.archived);
+
+                output.print_tasks(live);
+                if search.archived {
+                    output.print_notice("\n=== ARCHIVED TASKS ===");
+                    output.print_tasks(archived);
                 }
+            } else {
+                output.print_tasks(data);
             }
         }
-        TaskSubcommand::Archive(archive) => {
-            match archive_task(archive.id, dir.join(DB_FILE).to_str().unwrap()) {
-                Ok(()) => println!("Task {} archived successfully", archive.id),
-                Err(_) => eprintln!("Failed to archive task {}. Task may not exist.", archive.id),
+        TaskSubcommand::Repeat(repeat) => handle_repeat(repeat.command, db_file, &*output)?,
+        TaskSubcommand::Remind(remind) => {
+            let remind_at = set_reminder(remind.id, remind.to_reminder_time(), db_file)?;
+            println!(
+                "Reminder set for task {} at {}",
+                remind.id,
+                remind_at.format("%Y-%m-%d %H:%M")
+            );
+        }
+        TaskSubcommand::Depend(depend) => {
+            let ids: Vec<String> = depend.on.iter().map(u64::to_string).collect();
+            if depend.remove {
+                remove_dependencies(depend.id, &depend.on, db_file)?;
+                println!("Task {} no longer depends on {}", depend.id, ids.join(", "));
+            } else {
+                add_dependencies(depend.id, &depend.on, db_file)?;
+                println!("Task {} now depends on {}", depend.id, ids.join(", "));
             }
         }
-        TaskSubcommand::ViewArchive(view_archive) => {
-            if &view_archive.project[..] == "All" {
-                let results = get_all_archived_tasks(dir.join(DB_FILE).to_str().unwrap());
-                match results {
-                    Ok(data) => {
-                        if data.is_empty() {
-                            println!("No archived tasks found");
-                        } else {
-                            println!("\n=== ARCHIVED TASKS ===");
-                            let output = show_data(data);
-                            output.printstd();
-                        }
-                    }
-                    Err(_) => eprintln!("No database or archived data"),
-                }
+        TaskSubcommand::History(history) => {
+            let events = task_history(history.id, db_file)?;
+
+            if events.is_empty() && format == OutputFormat::Table {
+                output.print_notice(&format!("No history for task {}", history.id));
             } else {
-                let results = get_archived_tasks(
-                    &view_archive.project[..],
-                    dir.join(DB_FILE).to_str().unwrap(),
-                );
-                match results {
-                    Ok(data) => {
-                        if data.is_empty() {
-                            println!(
-                                "No archived tasks found for project: {}",
-                                view_archive.project
-                            );
-                        } else {
-                            println!("\n=== ARCHIVED TASKS: {} ===", view_archive.project);
-                            let output = show_data(data);
-                            output.printstd();
-                        }
-                    }
-                    Err(_) => eprintln!(
-                        "No database or archived data for project: {}",
-                        view_archive.project
-                    ),
+                output.print_events(events);
+            }
+        }
+        TaskSubcommand::Note(note) => handle_note(note.id, db_file)?,
+        TaskSubcommand::Show(show) => {
+            let task = match get_task(show.id, db_file) {
+                Err(TodoError::TaskNotFound(_)) => get_archived_task(show.id, db_file)?,
+                task => task?,
+            };
+
+            output.print_task(task);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the notes of a task with text from stdin or an editor, empty text clears them
+fn handle_note(id: u64, db_file: &str) -> Result<()> {
+    let current = get_task(id, db_file)?.notes.unwrap_or_default();
+    let notes = read_text(&current)?;
+    let changes = TaskChanges {
+        notes: Some(notes.trim_end().to_owned()),
+        ..TaskChanges::default()
+    };
+
+    update_task(id, &changes, db_file)?;
+    if changes.notes.as_deref() == Some("") {
+        println!("Notes for task {id} cleared");
+    } else {
+        println!("Notes for task {id} saved");
+    }
+
+    Ok(())
+}
+
+/// Updates or deletes one task, or several after showing which
+fn handle_update(
+    task: UpdateTask,
+    db_file: &str,
+    output: &dyn Output,
+    input: &mut dyn BufRead,
+) -> Result<()> {
+    let ids = task.task_ids();
+    if let [id] = ids[..] {
+        return update_one(id, task, db_file, input);
+    }
+
+    let targets = if ids.is_empty() {
+        query_tasks(&task.to_task_filter(), &preview_sort(), db_file)?
+    } else {
+        get_tasks_by_id(&task.ids, db_file)?
+    };
+    if targets.is_empty() {
+        println!("No tasks match");
+        return Ok(());
+    }
+
+    let ids: Vec<u64> = targets.iter().map(|target| target.id).collect();
+    let action = if task.delete { "Delete" } else { "Update" };
+    if !confirm_bulk(action, targets, task.yes, output, input)? {
+        println!("No tasks changed");
+        return Ok(());
+    }
+
+    if task.delete {
+        let deleted = delete_tasks(&ids, task.subtasks, db_file)?;
+        println!("Deleted {}", task_count(deleted));
+    } else {
+        let next_ids = update_tasks(&ids, &task.to_task_changes(), db_file)?;
+        println!("Updated {}", task_count(ids.len()));
+        for next_id in next_ids {
+            print_next_occurrence(next_id, db_file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates or deletes a single task
+fn update_one(id: u64, task: UpdateTask, db_file: &str, input: &mut dyn BufRead) -> Result<()> {
+    if task.delete {
+        let existing = get_task(id, db_file)?;
+        let question = match task.subtasks {
+            // Refuse before asking rather than after
+            _ if existing.subtasks_total == 0 => {
+                format!("Delete task {id} '{}'?", existing.task)
+            }
+            SubtaskAction::Refuse => return Err(TodoError::HasSubtasks(id)),
+            SubtaskAction::Cascade => {
+                format!("Delete task {id} '{}' and its subtasks?", existing.task)
+            }
+            SubtaskAction::Reparent => format!(
+                "Delete task {id} '{}' and move its subtasks up?",
+                existing.task
+            ),
+        };
+
+        if task.yes || confirm(&question, input)? {
+            let subtasks = delete_task(id, task.subtasks, db_file)?;
+            println!("Task {id} deleted{}", subtask_note(subtasks));
+        } else {
+            println!("Task {id} not deleted");
+        }
+    } else {
+        let next_id = update_task(id, &task.to_task_changes(), db_file)?;
+        println!("Task {id} updated");
+        if let Some(next_id) = next_id {
+            print_next_occurrence(next_id, db_file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Archives one task, or several after showing which, then applies the retention policy
+///
+/// The retention policy only applies once something was archived, so declining the
+/// question or matching nothing leaves the archive as it was.
+fn handle_archive(
+    archive: &ArchiveTask,
+    db_file: &str,
+    output: &dyn Output,
+    input: &mut dyn BufRead,
+) -> Result<()> {
+    let ids = archive.task_ids();
+    let archived = if let [id] = ids[..] {
+        let subtasks = archive_task(id, archive.subtasks, db_file)?;
+        println!("Task {id} archived successfully{}", subtask_note(subtasks));
+        subtasks + 1
+    } else {
+        let targets = if ids.is_empty() {
+            query_tasks(&archive.to_task_filter(), &preview_sort(), db_file)?
+        } else {
+            get_tasks_by_id(&archive.ids, db_file)?
+        };
+
+        if targets.is_empty() {
+            println!("No tasks to archive");
+            0
+        } else {
+            let ids: Vec<u64> = targets.iter().map(|target| target.id).collect();
+            if confirm_bulk("Archive", targets, archive.yes, output, input)? {
+                let archived = archive_tasks(&ids, archive.subtasks, db_file)?;
+                println!("Archived {}", task_count(archived));
+                archived
+            } else {
+                println!("No tasks archived");
+                0
+            }
+        }
+    };
+
+    if let Some(cutoff) = archive.retention.filter(|_| archived > 0) {
+        let filter = ArchiveFilter {
+            archived_before: Some(cutoff),
+            ..ArchiveFilter::default()
+        };
+        let ids: Vec<u64> = get_archived_tasks(&filter, db_file)?
+            .iter()
+            .map(|task| task.id)
+            .collect();
+
+        match purge_archived_tasks(&ids, db_file)? {
+            0 => {}
+            1 => println!("Purged 1 task archived before {cutoff}"),
+            count => println!("Purged {count} tasks archived before {cutoff}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Previews of bulk changes list tasks by id
+fn preview_sort() -> TaskSort {
+    TaskSort {
+        keys: vec![SortKey::Id],
+        reverse: false,
+    }
+}
+
+/// Shows the tasks a bulk command is about to change, then asks to go ahead unless `yes`
+fn confirm_bulk(
+    action: &str,
+    tasks: Vec<TodoView>,
+    yes: bool,
+    output: &dyn Output,
+    input: &mut dyn BufRead,
+) -> Result<bool> {
+    let question = format!("{action} {}?", task_count(tasks.len()));
+    output.print_tasks(tasks);
+
+    Ok(yes || confirm(&question, input)?)
+}
+
+/// Number of tasks as words e.g. `1 task` or `3 tasks`
+fn task_count(count: usize) -> String {
+    if count == 1 {
+        String::from("1 task")
+    } else {
+        format!("{count} tasks")
+    }
+}
+
+/// Handles subcommands for the archive as a whole
+///
+/// # Errors
+///
+/// Returns an error if the database path is not valid UTF-8, the export file can't be
+/// written or if any database operation fails
+pub fn handle_archived(
+    command: ArchiveSubcommand,
+    db_path: &Path,
+    format: OutputFormat,
+    timestamps: bool,
+) -> Result<()> {
+    let db_file = db_file(db_path)?;
+    let output = format.output(timestamps);
+
+    match command {
+        ArchiveSubcommand::Purge(purge) => {
+            let tasks = get_archived_tasks(&purge.to_archive_filter(), db_file)?;
+
+            if purge.dry_run {
+                // Machine readable formats still print an empty listing
+                if tasks.is_empty() && format == OutputFormat::Table {
+                    output.print_notice("No archived tasks to purge");
+                } else {
+                    output.print_notice("\n=== TASKS TO PURGE ===");
+                    output.print_tasks(tasks);
                 }
+                return Ok(());
+            }
+
+            if tasks.is_empty() {
+                println!("No archived tasks to purge");
+                return Ok(());
+            }
+
+            // Nothing is deleted unless the export was written
+            if let Some(path) = &purge.export {
+                fs::write(path, tasks_to_json(&tasks))?;
+            }
+
+            let ids: Vec<u64> = tasks.iter().map(|task| task.id).collect();
+            let purged = purge_archived_tasks(&ids, db_file)?;
+            let saved = purge
+                .export
+                .map(|path| format!(", saved to {}", path.display()))
+                .unwrap_or_default();
+            match purged {
+                1 => println!("Purged 1 archived task{saved}"),
+                count => println!("Purged {count} archived tasks{saved}"),
             }
         }
-        TaskSubcommand::Stats(numbers) => {
-            if numbers.pending && numbers.overdue {
-                println!(
-                    "{}",
-                    count_pending(dir.join(DB_FILE).to_str().unwrap()).unwrap()
-                );
-            } else if !numbers.pending && numbers.overdue {
-                println!(
-                    "{}",
-                    count_overdue(dir.join(DB_FILE).to_str().unwrap()).unwrap()
-                );
+    }
+
+    Ok(())
+}
+
+/// Handles reminder subcommands
+///
+/// # Errors
+///
+/// Returns an error if the database path is not valid UTF-8 or if any database operation fails
+pub fn handle_reminders(
+    command: RemindSubcommand,
+    db_path: &Path,
+    format: OutputFormat,
+    timestamps: bool,
+) -> Result<()> {
+    let db_file = db_file(db_path)?;
+    let output = format.output(timestamps);
+
+    match command {
+        RemindSubcommand::Check(check) => {
+            let now = Local::now().naive_local();
+            let mut delivered = Vec::new();
+
+            for reminder in due_reminders(now, db_file)? {
+                // A failed notification is retried on the next check
+                if let Some(command) = &check.notify {
+                    if let Err(err) = run_notify_command(command, &reminder) {
+                        eprintln!(
+                            "Reminder for task {} not delivered: {err}",
+                            reminder.task.id
+                        );
+                        continue;
+                    }
+                }
+
+                mark_reminder_delivered(reminder.id, now, db_file)?;
+                delivered.push(reminder.task);
+            }
+
+            if delivered.is_empty() && format == OutputFormat::Table {
+                output.print_notice("No reminders due");
             } else {
-                println!(
-                    "{}",
-                    count_pending(dir.join(DB_FILE).to_str().unwrap()).unwrap()
-                );
+                output.print_tasks(delivered);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the stats asked for, the pending count when none are
+fn handle_stats(numbers: &GetStats, db_file: &str, output: &dyn Output) -> Result<()> {
+    let mut stats = Vec::new();
+    if numbers.pending || !(numbers.overdue || numbers.avg_completion) {
+        stats.push(("pending", StatValue::Count(count_pending(db_file)?)));
+    }
+    if numbers.overdue {
+        stats.push(("overdue", StatValue::Count(count_overdue(db_file)?)));
+    }
+    if numbers.avg_completion {
+        stats.push((
+            "avg_days_to_complete",
+            StatValue::Days(average_days_to_complete(db_file)?),
+        ));
+    }
+
+    output.print_stats(&stats);
+
+    Ok(())
+}
+
+/// Shows recent changes across all tasks
+///
+/// # Errors
+///
+/// Returns an error if the database path is not valid UTF-8 or if any database operation fails
+pub fn handle_log(log: &LogOptions, db_path: &Path, format: OutputFormat) -> Result<()> {
+    let db_file = db_file(db_path)?;
+    let output = format.output(false);
+
+    let events = recent_events(log.limit, db_file)?;
+    if events.is_empty() && format == OutputFormat::Table {
+        output.print_notice("No changes recorded yet");
+    } else {
+        output.print_events(events);
+    }
+
+    Ok(())
+}
+
+/// Undoes the most recent commands and says what was undone
+///
+/// # Errors
+///
+/// Returns an error if the database path is not valid UTF-8 or if any database operation fails
+pub fn handle_undo(options: &UndoOptions, db_path: &Path) -> Result<()> {
+    let db_file = db_file(db_path)?;
+
+    let undone = undo(options.steps, db_file)?;
+    if undone.is_empty() {
+        println!("Nothing to undo");
+    }
+
+    for operation in undone {
+        println!(
+            "Undid changes from {}:",
+            operation.at.format("%Y-%m-%d %H:%M")
+        );
+        if operation.events.is_empty() {
+            println!("  changes to series, dependencies or reminders");
+        }
+        for event in operation.events.iter().rev() {
+            println!(
+                "  {} of task {} '{}' ({})",
+                event.action,
+                event.task_id,
+                event.task,
+                event_changes(event)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles recurring series subcommands
+fn handle_repeat(command: RepeatSubcommand, db_file: &str, output: &dyn Output) -> Result<()> {
+    match command {
+        RepeatSubcommand::List => output.print_series(get_series(db_file)?),
+        RepeatSubcommand::Pause(series) => {
+            set_series_paused(series.id, true, db_file)?;
+            println!("Series {} paused", series.id);
+        }
+        RepeatSubcommand::Resume(series) => {
+            let next_id = set_series_paused(series.id, false, db_file)?;
+            println!("Series {} resumed", series.id);
+            if let Some(next_id) = next_id {
+                print_next_occurrence(next_id, db_file)?;
+            }
+        }
+        RepeatSubcommand::End(series) => {
+            end_series(series.id, db_file)?;
+            println!("Series {} ended", series.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Mentions subtasks removed along with a task
+fn subtask_note(count: usize) -> String {
+    match count {
+        0 => String::new(),
+        1 => String::from(" along with 1 subtask"),
+        count => format!(" along with {count} subtasks"),
+    }
+}
+
+/// Tells the user about the occurrence added for a recurring task
+fn print_next_occurrence(task_id: u64, db_file: &str) -> Result<()> {
+    let next = get_task(task_id, db_file)?;
+    let due_date = next
+        .due_date
+        .map_or_else(String::new, |date| format!(", due {date}"));
+    println!("Added next occurrence as task {task_id}{due_date}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_path_prefers_xdg_data_home() {
+        assert_eq!(
+            Some(PathBuf::from("/data/todo/todo.db")),
+            default_db_path(Some("/data".into()), Some("/home/me".into()))
+        );
+    }
+
+    #[test]
+    fn default_path_falls_back_to_home() {
+        assert_eq!(
+            Some(PathBuf::from("/home/me/.local/share/todo/todo.db")),
+            default_db_path(None, Some("/home/me".into()))
+        );
+
+        // Relative XDG paths are invalid per the spec and must be ignored
+        assert_eq!(
+            Some(PathBuf::from("/home/me/.local/share/todo/todo.db")),
+            default_db_path(Some("data".into()), Some("/home/me".into()))
+        );
+    }
+
+    #[test]
+    fn default_path_needs_a_home() {
+        assert_eq!(None, default_db_path(None, None));
+    }
+
+    #[test]
+    fn declined_archive_skips_retention() {
+        use crate::{
+            args::{Cli, OptionTypes, TaskOptions},
+            database::{get_all_archived_tasks, get_all_tasks, open, test_database, TodoData},
+        };
+        use clap::Parser;
+
+        let test_db = test_database("declined_archive_skips_retention");
+        for task in ["Old", "Kept", "Also kept"] {
+            TodoData {
+                project: String::from("Work"),
+                task: String::from(task),
+                ..TodoData::default()
             }
+            .write_data(&test_db)
+            .unwrap();
         }
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+        open(&test_db)
+            .unwrap()
+            .execute(
+                "UPDATE archived_data SET archived_date = '2020-03-01 10:00:00' WHERE id = 1",
+                (),
+            )
+            .unwrap();
+
+        let cli =
+            Cli::try_parse_from(["todo", "task", "archive", "2-3", "--retention", "1d"]).unwrap();
+        let OptionTypes::Task(TaskOptions {
+            command: TaskSubcommand::Archive(archive),
+        }) = cli.options
+        else {
+            unreachable!("parsed a task archive")
+        };
+
+        let output = OutputFormat::Table.output(false);
+        handle_archive(&archive, &test_db, &*output, &mut &b"n\n"[..]).unwrap();
+
+        let archived = get_all_archived_tasks(&test_db).unwrap();
+        assert_eq!(
+            vec![1],
+            archived.iter().map(|task| task.id).collect::<Vec<_>>()
+        );
+        assert_eq!(2, get_all_tasks(&test_db).unwrap().len());
     }
 }
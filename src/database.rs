@@ -1,11 +1,183 @@
-use crate::args::UpdateTask;
-use rusqlite::{named_params, params, Connection, Result};
+pub mod history;
+pub mod migrations;
+pub mod undo;
+
+use std::{fmt, path::Path};
+
+use self::history::EventAction;
+use crate::{
+    dates::DEFAULT_REMINDER_TIME,
+    error::{Result, TodoError},
+    ids::{expand_ids, IdRange},
+    recurrence::Recurrence,
+};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta};
+use clap::ValueEnum;
+use rusqlite::{
+    named_params, params, params_from_iter,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Value, ValueRef},
+    Connection, OptionalExtension, Row, ToSql, Transaction,
+};
+use serde::Serialize;
+
+/// Opens the database, creating it if needed, and applies any pending schema migrations
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be opened or migrated
+pub fn open(db_file: &str) -> Result<Connection> {
+    let mut conn = Connection::open(db_file)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    migrations::migrate(&mut conn)?;
+
+    Ok(conn)
+}
+
+/// Opens an existing database, applying any pending schema migrations
+///
+/// # Errors
+///
+/// Returns `TodoError::MissingDatabase` if the file does not exist, or an error if the
+/// database cannot be opened or migrated
+pub fn open_existing(db_file: &str) -> Result<Connection> {
+    if !Path::new(db_file).exists() {
+        return Err(TodoError::MissingDatabase(db_file.to_owned()));
+    }
+
+    open(db_file)
+}
+
+/// Columns selected for every task listing, in the order `TodoView::from_row` expects
+const TASK_COLUMNS: &str = "id, project, task, due_date, complete, priority, \
+    (SELECT group_concat(name, ',' ORDER BY name) FROM task_tags \
+        JOIN tags ON tags.id = task_tags.tag_id WHERE task_tags.task_id = data.id) AS tags, \
+    0 AS archived, parent_id, \
+    (SELECT COUNT(*) FROM data AS sub WHERE sub.parent_id = data.id) AS subtasks_total, \
+    (SELECT COUNT(*) FROM data AS sub WHERE sub.parent_id = data.id AND sub.complete = 1) \
+        AS subtasks_done, \
+    (SELECT group_concat(depends_on_id, ',' ORDER BY depends_on_id) FROM task_dependencies \
+        JOIN data AS prerequisite ON prerequisite.id = task_dependencies.depends_on_id \
+        WHERE task_dependencies.task_id = data.id AND prerequisite.complete = 0) AS blocked_by, \
+    created_at, updated_at, completed_at, NULL AS archived_at, notes";
+
+/// Archived tasks keep a snapshot of their tags instead of links to the `tags` table
+const ARCHIVED_TASK_COLUMNS: &str = "id, project, task, due_date, complete, priority, tags, \
+    1 AS archived, parent_id, 0, 0, NULL, created_at, updated_at, completed_at, archived_date, notes";
+
+/// Id for a new task, above any id a task ever had so archived tasks and the history of
+/// deleted tasks keep theirs to themselves
+const NEXT_TASK_ID: &str = "(SELECT max(
+        coalesce((SELECT max(id) FROM data), 0),
+        coalesce((SELECT max(id) FROM archived_data), 0),
+        coalesce((SELECT max(task_id) FROM task_events), 0)
+    ) + 1)";
+
+/// Current local time in the format timestamps are stored in
+const NOW: &str = "datetime('now', 'localtime')";
+
+/// How important a task is, stored as 1 (low) to 4 (urgent)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[value(alias = "1")]
+    Low = 1,
+    #[default]
+    #[value(alias = "2")]
+    Medium = 2,
+    #[value(alias = "3")]
+    High = 3,
+    #[value(alias = "4")]
+    Urgent = 4,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Urgent => "urgent",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl ToSql for Priority {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as i64))
+    }
+}
+
+impl FromSql for Priority {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(Self::Low),
+            2 => Ok(Self::Medium),
+            3 => Ok(Self::High),
+            4 => Ok(Self::Urgent),
+            other => Err(FromSqlError::OutOfRange(other)),
+        }
+    }
+}
 
+#[derive(Default)]
 pub struct TodoData {
     pub project: String,
     pub task: String,
-    pub due_date: String,
+    pub due_date: NaiveDate,
     pub complete: bool,
+    pub priority: Priority,
+    pub tags: Vec<String>,
+    /// Makes the task the first occurrence of a new recurring series
+    pub repeat: Option<Recurrence>,
+    /// Makes the task a subtask of this task
+    pub parent_id: Option<u64>,
+}
+
+/// Splits `+tag` words out of a task description, returning the cleaned description and tags
+#[must_use]
+pub fn extract_inline_tags(description: &str) -> (String, Vec<String>) {
+    let mut words = Vec::new();
+    let mut tags = Vec::new();
+
+    for word in description.split_whitespace() {
+        match word.strip_prefix('+') {
+            Some(tag) if tag.starts_with(|c: char| c.is_alphabetic()) => {
+                tags.push(tag.to_owned());
+            }
+            _ => words.push(word),
+        }
+    }
+
+    (words.join(" "), tags)
+}
+
+/// Normalises tag names so `Work` and `work ` are the same tag
+fn normalise_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Links tags to a task, creating any tags that don't exist yet
+fn add_tags(tx: &Transaction, task_id: i64, tags: &[String]) -> Result<()> {
+    for tag in tags.iter().map(|tag| normalise_tag(tag)) {
+        if tag.is_empty() {
+            continue;
+        }
+
+        tx.execute(
+            "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+            params![tag],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id)
+            SELECT ?1, id FROM tags WHERE name = ?2",
+            params![task_id, tag],
+        )?;
+    }
+
+    Ok(())
 }
 
 impl TodoData {
@@ -14,143 +186,1162 @@ impl TodoData {
     /// # Errors
     ///
     /// Returns an error if database operations fail
-    ///
-    /// # Panics
-    ///
-    /// This function will panic if the database connection cannot be established
     pub fn write_data(self, db_file: &str) -> Result<()> {
-        let mut conn = Connection::open(db_file).unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS data (
-                id INTEGER PRIMARY KEY NOT NULL,
-                project VARCHAR(50) NOT NULL,
-                task VARCHAR(100) NOT NULL,
-                due_date DATE,
-                complete BOOLEAN NOT NULL CHECK (complete IN (0, 1))
-            );",
-            (),
-        )?;
+        let mut conn = open(db_file)?;
 
         let tx = conn.transaction()?;
+        let operation = undo::begin_operation(&tx)?;
+
+        if let Some(parent_id) = self.parent_id {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM data WHERE id = ?1)",
+                params![parent_id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(TodoError::TaskNotFound(parent_id));
+            }
+        }
+
+        let recurrence_id = if let Some(rule) = self.repeat {
+            tx.execute("INSERT INTO recurrences (rule) VALUES (?1)", params![rule])?;
+            Some(tx.last_insert_rowid())
+        } else {
+            None
+        };
+
         tx.execute(
-            "INSERT INTO data (project, task, due_date, complete)
-            VALUES (:project, :task, :due_date, :complete)",
+            &format!(
+                "INSERT INTO data (id, project, task, due_date, complete, priority,
+                    recurrence_id, parent_id, created_at, updated_at, completed_at)
+                VALUES ({NEXT_TASK_ID}, :project, :task, :due_date, :complete, :priority,
+                    :recurrence_id, :parent_id, {NOW}, {NOW}, CASE WHEN :complete THEN {NOW} END)"
+            ),
             named_params! {
                 ":project": self.project,
                 ":task": self.task,
                 ":due_date": self.due_date,
                 ":complete": i32::from(self.complete),
+                ":priority": self.priority,
+                ":recurrence_id": recurrence_id,
+                ":parent_id": self.parent_id,
             },
         )?;
+        let task_id = tx.last_insert_rowid();
+        add_tags(&tx, task_id, &self.tags)?;
+        record_created(&tx, task_id)?;
 
+        undo::finish_operation(&tx, operation)?;
         tx.commit()?;
 
         Ok(())
     }
+}
+
+/// Records a task that was just added in its history
+fn record_created(tx: &Transaction, task_id: i64) -> Result<()> {
+    let task_id = u64::try_from(task_id).unwrap_or_default();
+    let state = history::task_state(tx, task_id)?;
+
+    history::record(tx, task_id, EventAction::Create, None, state.as_ref())
+}
+
+/// Changes to apply to an existing task, `None` leaves the column untouched
+#[derive(Debug, Default)]
+pub struct TaskChanges {
+    pub project: Option<String>,
+    pub task: Option<String>,
+    pub due_date: Option<NaiveDate>,
+    pub complete: Option<bool>,
+    pub priority: Option<Priority>,
+    /// An empty string clears the notes
+    pub notes: Option<String>,
+}
+
+/// Adds the next occurrence of a recurring task, unless its series is paused or already
+/// has a pending occurrence
+///
+/// Returns the id of the new task.
+fn schedule_next_occurrence(tx: &Transaction, task_id: u64) -> Result<Option<u64>> {
+    let series = tx
+        .query_row(
+            "SELECT rule, due_date FROM data
+            JOIN recurrences ON recurrences.id = data.recurrence_id
+            WHERE data.id = ?1 AND paused = 0
+                AND NOT EXISTS (SELECT 1 FROM data WHERE recurrence_id = recurrences.id
+                    AND complete = 0)",
+            params![task_id],
+            |row| {
+                Ok((
+                    row.get::<_, Recurrence>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let Some((rule, due_date)) = series else {
+        return Ok(None);
+    };
+
+    let today = Local::now().date_naive();
+    let due_date = due_date
+        .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+        .unwrap_or(today);
+    let Some(next_due) = rule.next_due(due_date, today) else {
+        return Ok(None);
+    };
+
+    tx.execute(
+        &format!(
+            "INSERT INTO data (id, project, task, due_date, complete, priority, recurrence_id,
+                parent_id, created_at, updated_at)
+            SELECT {NEXT_TASK_ID}, project, task, ?2, 0, priority, recurrence_id, parent_id,
+                {NOW}, {NOW}
+            FROM data WHERE id = ?1"
+        ),
+        params![task_id, next_due],
+    )?;
+    let next_id = tx.last_insert_rowid();
+    tx.execute(
+        "INSERT INTO task_tags (task_id, tag_id) SELECT ?1, tag_id FROM task_tags WHERE task_id = ?2",
+        params![next_id, task_id],
+    )?;
+    record_created(tx, next_id)?;
+
+    Ok(u64::try_from(next_id).ok())
+}
+
+/// Updates the supplied columns of a task in a single transaction
+///
+/// Completing a recurring task adds its next occurrence, whose id is returned.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, or an error if database
+/// operations fail
+pub fn update_task(task_id: u64, changes: &TaskChanges, db_file: &str) -> Result<Option<u64>> {
+    Ok(update_tasks(&[task_id], changes, db_file)?.pop())
+}
+
+/// Applies the same changes to several tasks in a single transaction
+///
+/// Returns the ids of the occurrences added for recurring tasks that were completed.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if any of the tasks don't exist, or an error if
+/// database operations fail. No task is changed when an error is returned.
+pub fn update_tasks(task_ids: &[u64], changes: &TaskChanges, db_file: &str) -> Result<Vec<u64>> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let mut next_ids = Vec::new();
+    for &task_id in task_ids {
+        next_ids.extend(update_in(&tx, task_id, changes)?);
+    }
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(next_ids)
+}
+
+/// Updates the supplied columns of a task, returning the id of the occurrence added when a
+/// recurring task is completed
+fn update_in(tx: &Transaction, task_id: u64, changes: &TaskChanges) -> Result<Option<u64>> {
+    let complete = changes.complete.map(i32::from);
+    let mut assignments = Vec::new();
+    let mut values: Vec<(&str, &dyn ToSql)> = vec![(":id", &task_id)];
+
+    if let Some(project) = &changes.project {
+        assignments.push("project = :project");
+        values.push((":project", project));
+    }
+    if let Some(task) = &changes.task {
+        assignments.push("task = :task");
+        values.push((":task", task));
+    }
+    if let Some(due_date) = &changes.due_date {
+        assignments.push("due_date = :due_date");
+        values.push((":due_date", due_date));
+    }
+    // Keep the original completion time when a complete task is completed again
+    let completion = format!(
+        "complete = :complete, completed_at = CASE WHEN NOT :complete THEN NULL \
+            WHEN complete = 1 THEN completed_at ELSE {NOW} END"
+    );
+    if let Some(complete) = &complete {
+        assignments.push(completion.as_str());
+        values.push((":complete", complete));
+    }
+    if let Some(priority) = &changes.priority {
+        assignments.push("priority = :priority");
+        values.push((":priority", priority));
+    }
+    if let Some(notes) = &changes.notes {
+        assignments.push("notes = NULLIF(:notes, '')");
+        values.push((":notes", notes));
+    }
+
+    let Some(before) = history::task_state(tx, task_id)? else {
+        return Err(TodoError::TaskNotFound(task_id));
+    };
+    let was_complete = before.get("complete") == Some(&serde_json::Value::Bool(true));
+
+    // With nothing to change finding the task is all there is to do
+    if assignments.is_empty() {
+        return Ok(None);
+    }
+
+    let sql = format!(
+        "UPDATE data SET {}, updated_at = {NOW} WHERE id = :id",
+        assignments.join(", ")
+    );
+
+    if tx.execute(&sql, values.as_slice())? == 0 {
+        return Err(TodoError::TaskNotFound(task_id));
+    }
+    history::record_update(tx, task_id, &before)?;
+
+    if changes.complete == Some(true) && !was_complete {
+        schedule_next_occurrence(tx, task_id)
+    } else {
+        Ok(None)
+    }
+}
+
+/// What to do with the subtasks of a task that is deleted or archived
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SubtaskAction {
+    /// Leave everything as it is when the task has subtasks
+    #[default]
+    Refuse,
+    /// Delete or archive the subtasks, and theirs, along with the task
+    Cascade,
+    /// Move the subtasks up to the task's own parent
+    Reparent,
+}
+
+/// Applies `action` to the subtasks of a task about to be removed
+///
+/// Returns the ids of every task to remove, the task itself first.
+fn detach_subtasks(tx: &Transaction, task_id: u64, action: SubtaskAction) -> Result<Vec<u64>> {
+    let mut stmt = tx.prepare(
+        "WITH RECURSIVE subtree (id) AS (
+            SELECT id FROM data WHERE parent_id = ?1
+            UNION SELECT data.id FROM data JOIN subtree ON data.parent_id = subtree.id
+        )
+        SELECT id FROM subtree",
+    )?;
+    let descendants = stmt
+        .query_map(params![task_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<u64>>>()?;
+
+    let mut ids = vec![task_id];
+    match action {
+        _ if descendants.is_empty() => {}
+        SubtaskAction::Refuse => return Err(TodoError::HasSubtasks(task_id)),
+        SubtaskAction::Cascade => ids.extend(descendants),
+        SubtaskAction::Reparent => {
+            let mut stmt = tx.prepare("SELECT id FROM data WHERE parent_id = ?1")?;
+            let children = stmt
+                .query_map(params![task_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<u64>>>()?;
+            let before = children
+                .iter()
+                .map(|&id| history::task_state(tx, id))
+                .collect::<Result<Vec<_>>>()?;
 
-    /// Updates a task in the database
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if database operations fail
-    ///
-    /// # Panics
-    ///
-    /// This function will panic if the database connection cannot be established
-    pub fn update_task(self, update_task: UpdateTask, db_file: &str) -> Result<()> {
-        if update_task.complete && update_task.delete {
-            println!("Cannot delete and update a task");
-        } else if update_task.complete {
-            let mut conn = Connection::open(db_file).unwrap();
-
-            let tx = conn.transaction()?;
             tx.execute(
-                "UPDATE data
-                SET complete = :complete
-                WHERE id = :id",
-                named_params! {
-                    ":id": update_task.id,
-                    ":complete": i32::from(self.complete),
-                },
+                &format!(
+                    "UPDATE data SET parent_id = (SELECT parent_id FROM data WHERE id = ?1),
+                        updated_at = {NOW}
+                    WHERE parent_id = ?1"
+                ),
+                params![task_id],
             )?;
 
-            tx.commit()?;
-        } else {
-            let mut conn = Connection::open(db_file).unwrap();
+            for (&id, before) in children.iter().zip(before.iter().flatten()) {
+                history::record_update(tx, id, before)?;
+            }
+        }
+    }
 
-            let tx = conn.transaction()?;
-            tx.execute(
-                "DELETE FROM data
-                WHERE id = :id",
-                named_params! {
-                    ":id": update_task.id,
-                },
+    Ok(ids)
+}
+
+/// Records tasks about to be deleted or archived in their history
+fn record_removed(tx: &Transaction, ids: &[u64], action: EventAction) -> Result<()> {
+    for &id in ids {
+        if let Some(state) = history::task_state(tx, id)? {
+            history::record(tx, id, action, Some(&state), None)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Positional placeholders for a list of `count` values, e.g. `?1, ?2, ?3`
+fn placeholders(count: usize) -> String {
+    (1..=count)
+        .map(|index| format!("?{index}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Deletes a task from the database, handling its subtasks as `subtasks` says
+///
+/// Returns the number of subtasks deleted along with the task.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, `TodoError::HasSubtasks` if
+/// it has subtasks and `subtasks` is `Refuse`, or an error if database operations fail
+pub fn delete_task(task_id: u64, subtasks: SubtaskAction, db_file: &str) -> Result<usize> {
+    Ok(delete_tasks(&[task_id], subtasks, db_file)? - 1)
+}
+
+/// Deletes several tasks in a single transaction, handling subtasks that aren't in the list
+/// as `subtasks` says
+///
+/// Returns the number of tasks deleted, subtasks included.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if any of the tasks don't exist,
+/// `TodoError::HasSubtasks` if one has subtasks that aren't deleted too and `subtasks` is
+/// `Refuse`, or an error if database operations fail. Nothing is deleted when an error is
+/// returned.
+pub fn delete_tasks(task_ids: &[u64], subtasks: SubtaskAction, db_file: &str) -> Result<usize> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let mut deleted = 0;
+    for task_id in subtasks_first(&tx, task_ids)? {
+        // Subtasks deleted along with an earlier task are gone already
+        if task_exists(&tx, task_id)? {
+            let ids = detach_subtasks(&tx, task_id, subtasks)?;
+            record_removed(&tx, &ids, EventAction::Delete)?;
+
+            deleted += tx.execute(
+                &format!("DELETE FROM data WHERE id IN ({})", placeholders(ids.len())),
+                params_from_iter(&ids),
             )?;
+        }
+    }
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(deleted)
+}
+
+/// Orders tasks so subtasks come before their parents, a parent whose subtasks are all in
+/// the list then has none left by its turn
+///
+/// Returns `TodoError::TaskNotFound` for the first task that doesn't exist.
+fn subtasks_first(tx: &Transaction, task_ids: &[u64]) -> Result<Vec<u64>> {
+    let mut stmt = tx.prepare(&format!(
+        "WITH RECURSIVE depth (id, level) AS (
+            SELECT id, 0 FROM data WHERE parent_id IS NULL
+            UNION ALL SELECT data.id, depth.level + 1 FROM data
+                JOIN depth ON data.parent_id = depth.id
+        )
+        SELECT id FROM depth WHERE id IN ({})
+        ORDER BY level DESC, id;",
+        placeholders(task_ids.len())
+    ))?;
+    let ordered = stmt
+        .query_map(params_from_iter(task_ids), |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<u64>>>()?;
+
+    if let Some(&missing) = task_ids.iter().find(|id| !ordered.contains(id)) {
+        return Err(TodoError::TaskNotFound(missing));
+    }
+
+    Ok(ordered)
+}
+
+/// Whether a live task exists
+fn task_exists(conn: &Connection, task_id: u64) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM data WHERE id = ?1)",
+        params![task_id],
+        |row| row.get(0),
+    )?)
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TodoView {
+    pub id: u64,
+    pub project: String,
+    pub task: String,
+    /// `None` when the task has no due date or the stored date is malformed
+    pub due_date: Option<NaiveDate>,
+    pub complete: bool,
+    pub priority: Priority,
+    pub tags: Vec<String>,
+    /// Whether the task comes from the archive
+    pub archived: bool,
+    pub parent_id: Option<u64>,
+    /// Number of direct subtasks
+    pub subtasks_total: u32,
+    /// Number of direct subtasks that are complete
+    pub subtasks_done: u32,
+    /// Prerequisites that are not complete yet
+    pub blocked_by: Vec<u64>,
+    /// Local time the task was added, `None` for tasks added by older versions
+    pub created_at: Option<NaiveDateTime>,
+    /// Local time the task was last changed
+    pub updated_at: Option<NaiveDateTime>,
+    /// Local time the task was completed, `None` while it is pending
+    pub completed_at: Option<NaiveDateTime>,
+    /// Local time the task was archived, `None` for live tasks
+    pub archived_at: Option<NaiveDateTime>,
+    /// Free-form notes, possibly several lines long
+    pub notes: Option<String>,
+}
+
+impl TodoView {
+    /// Maps a row selected as `TASK_COLUMNS`
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let due_date: Option<String> = row.get(3)?;
 
-            tx.commit()?;
+        Ok(Self {
+            id: row.get(0)?,
+            project: row.get(1)?,
+            task: row.get(2)?,
+            due_date: due_date.and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+            complete: matches!(row.get::<_, i32>(4)?, 1),
+            priority: row.get(5)?,
+            tags: row
+                .get::<_, Option<String>>(6)?
+                .map(|tags| tags.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+            archived: row.get(7)?,
+            parent_id: row.get(8)?,
+            subtasks_total: row.get(9)?,
+            subtasks_done: row.get(10)?,
+            blocked_by: row
+                .get::<_, Option<String>>(11)?
+                .map(|ids| ids.split(',').filter_map(|id| id.parse().ok()).collect())
+                .unwrap_or_default(),
+            created_at: row.get(12)?,
+            updated_at: row.get(13)?,
+            completed_at: row.get(14)?,
+            archived_at: row.get(15)?,
+            notes: row.get(16)?,
+        })
+    }
+}
+
+/// A recurring series and its latest occurrence
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Series {
+    pub id: u64,
+    #[serde(rename = "repeat")]
+    pub rule: Recurrence,
+    pub paused: bool,
+    /// The pending occurrence, or the last one when none are pending
+    pub task_id: Option<u64>,
+    pub project: Option<String>,
+    pub task: Option<String>,
+    pub due_date: Option<NaiveDate>,
+}
+
+/// Gets every recurring series
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn get_series(db_file: &str) -> Result<Vec<Series>> {
+    let conn = open_existing(db_file)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT recurrences.id, rule, paused, data.id, project, task, due_date
+        FROM recurrences LEFT JOIN data ON data.id = (
+            SELECT id FROM data WHERE recurrence_id = recurrences.id
+            ORDER BY complete, id DESC LIMIT 1
+        )
+        ORDER BY recurrences.id;",
+    )?;
+
+    let series_iter = stmt.query_map([], |row| {
+        let due_date: Option<String> = row.get(6)?;
+
+        Ok(Series {
+            id: row.get(0)?,
+            rule: row.get(1)?,
+            paused: row.get(2)?,
+            task_id: row.get(3)?,
+            project: row.get(4)?,
+            task: row.get(5)?,
+            due_date: due_date.and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+        })
+    })?;
+
+    let mut result = Vec::new();
+
+    for series in series_iter {
+        result.push(series?);
+    }
+
+    Ok(result)
+}
+
+/// Pauses or resumes a recurring series, completing a task in a paused series adds no
+/// next occurrence
+///
+/// Resuming a series whose last occurrence was completed while paused adds the next
+/// occurrence, whose id is returned.
+///
+/// # Errors
+///
+/// Returns `TodoError::SeriesNotFound` if the series doesn't exist, or an error if database
+/// operations fail
+pub fn set_series_paused(series_id: u64, paused: bool, db_file: &str) -> Result<Option<u64>> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let changed = tx.execute(
+        "UPDATE recurrences SET paused = ?2 WHERE id = ?1",
+        params![series_id, paused],
+    )?;
+
+    if changed == 0 {
+        return Err(TodoError::SeriesNotFound(series_id));
+    }
+
+    let last_task: Option<u64> = tx.query_row(
+        "SELECT MAX(id) FROM data WHERE recurrence_id = ?1",
+        params![series_id],
+        |row| row.get(0),
+    )?;
+    let next_id = match last_task {
+        Some(task_id) if !paused => schedule_next_occurrence(&tx, task_id)?,
+        _ => None,
+    };
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(next_id)
+}
+
+/// Ends a recurring series, its tasks are kept as ordinary tasks
+///
+/// # Errors
+///
+/// Returns `TodoError::SeriesNotFound` if the series doesn't exist, or an error if database
+/// operations fail
+pub fn end_series(series_id: u64, db_file: &str) -> Result<()> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let deleted = tx.execute("DELETE FROM recurrences WHERE id = ?1", params![series_id])?;
+
+    if deleted == 0 {
+        return Err(TodoError::SeriesNotFound(series_id));
+    }
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Makes a task depend on other tasks, so it stays blocked until they are complete
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if any of the tasks don't exist,
+/// `TodoError::DependencyCycle` if a prerequisite already depends on the task, directly or
+/// through other tasks, or an error if database operations fail
+pub fn add_dependencies(task_id: u64, prerequisites: &[u64], db_file: &str) -> Result<()> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    for &id in std::iter::once(&task_id).chain(prerequisites) {
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM data WHERE id = ?1)",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(TodoError::TaskNotFound(id));
         }
-        Ok(())
     }
 
-    /// Archives the task
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if archiving fails
-    pub fn archive(self) -> Result<()> {
-        // is this a requirement?
-        // perhaps needs to push to online db?
-        // maybe needs an api as a microservice
-        todo!();
+    for &prerequisite in prerequisites {
+        // Follow what the prerequisite depends on, a path back to the task would be a cycle
+        let cycle: bool = tx.query_row(
+            "WITH RECURSIVE upstream (id) AS (
+                SELECT ?1
+                UNION SELECT depends_on_id FROM task_dependencies
+                    JOIN upstream ON task_dependencies.task_id = upstream.id
+            )
+            SELECT EXISTS (SELECT 1 FROM upstream WHERE id = ?2)",
+            params![prerequisite, task_id],
+            |row| row.get(0),
+        )?;
+        if cycle {
+            return Err(TodoError::DependencyCycle(task_id, prerequisite));
+        }
+
+        tx.execute(
+            "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on_id) VALUES (?1, ?2)",
+            params![task_id, prerequisite],
+        )?;
+    }
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Removes dependencies of a task, prerequisites it doesn't depend on are ignored
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, or an error if database
+/// operations fail
+pub fn remove_dependencies(task_id: u64, prerequisites: &[u64], db_file: &str) -> Result<()> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let exists: bool = tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM data WHERE id = ?1)",
+        params![task_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(TodoError::TaskNotFound(task_id));
+    }
+
+    for prerequisite in prerequisites {
+        tx.execute(
+            "DELETE FROM task_dependencies WHERE task_id = ?1 AND depends_on_id = ?2",
+            params![task_id, prerequisite],
+        )?;
     }
 
-    /// Sets a reminder for the task
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if setting reminder fails
-    pub fn set_reminder(self) -> Result<()> {
-        todo!();
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// When a reminder should go off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReminderTime {
+    /// At a fixed date and time
+    At(NaiveDateTime),
+    /// This long before the task is due, due dates count from `DEFAULT_REMINDER_TIME`
+    BeforeDue(TimeDelta),
+}
+
+/// Sets a reminder for a task
+///
+/// Returns the local date and time the reminder will go off.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, `TodoError::InvalidDate` if
+/// the reminder is relative to the due date and the task has none, or an error if database
+/// operations fail
+pub fn set_reminder(task_id: u64, time: ReminderTime, db_file: &str) -> Result<NaiveDateTime> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let due_date: Option<String> = tx
+        .query_row(
+            "SELECT due_date FROM data WHERE id = ?1",
+            params![task_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(TodoError::TaskNotFound(task_id))?;
+
+    let remind_at = match time {
+        ReminderTime::At(remind_at) => remind_at,
+        ReminderTime::BeforeDue(before) => due_date
+            .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+            .and_then(|date| {
+                date.and_time(DEFAULT_REMINDER_TIME)
+                    .checked_sub_signed(before)
+            })
+            .ok_or_else(|| {
+                TodoError::InvalidDate(format!(
+                    "task {task_id} has no due date to set a reminder before"
+                ))
+            })?,
+    };
+
+    tx.execute(
+        "INSERT INTO reminders (task_id, remind_at) VALUES (?1, ?2)",
+        params![task_id, remind_at],
+    )?;
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(remind_at)
+}
+
+/// A reminder that has gone off, with its task
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reminder {
+    pub id: u64,
+    pub remind_at: NaiveDateTime,
+    pub task: TodoView,
+}
+
+/// Gets reminders for pending tasks that are due by `now` and haven't been delivered
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn due_reminders(now: NaiveDateTime, db_file: &str) -> Result<Vec<Reminder>> {
+    let conn = open_existing(db_file)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TASK_COLUMNS}, due.reminder_id, due.remind_at
+        FROM (SELECT id AS reminder_id, task_id, remind_at FROM reminders
+            WHERE delivered_at IS NULL AND remind_at <= ?1) AS due
+        JOIN data ON data.id = due.task_id
+        WHERE complete = 0
+        ORDER BY due.remind_at, due.reminder_id;"
+    ))?;
+
+    let reminders_iter = stmt.query_map(params![now], |row| {
+        Ok(Reminder {
+            id: row.get("reminder_id")?,
+            remind_at: row.get("remind_at")?,
+            task: TodoView::from_row(row)?,
+        })
+    })?;
+
+    let mut result = Vec::new();
+
+    for reminder in reminders_iter {
+        result.push(reminder?);
+    }
+
+    Ok(result)
+}
+
+/// Marks a reminder as delivered so it doesn't go off again
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn mark_reminder_delivered(
+    reminder_id: u64,
+    delivered_at: NaiveDateTime,
+    db_file: &str,
+) -> Result<()> {
+    let conn = open_existing(db_file)?;
+
+    conn.execute(
+        "UPDATE reminders SET delivered_at = ?2 WHERE id = ?1",
+        params![reminder_id, delivered_at],
+    )?;
+
+    Ok(())
+}
+
+/// Gets a single task
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, or an error if database
+/// operations fail
+pub fn get_task(task_id: u64, db_file: &str) -> Result<TodoView> {
+    let conn = open_existing(db_file)?;
+
+    conn.query_row(
+        &format!("SELECT {TASK_COLUMNS} FROM data WHERE id = ?1"),
+        params![task_id],
+        TodoView::from_row,
+    )
+    .optional()?
+    .ok_or(TodoError::TaskNotFound(task_id))
+}
+
+/// Gets the tasks given by id or range, in the order given and without repeats
+///
+/// Archived and deleted tasks leave gaps in the ids, so ids inside a range that match no
+/// task are skipped. An id given on its own must exist.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if an id given on its own doesn't exist or no task
+/// matches at all, or an error if database operations fail
+pub fn get_tasks_by_id(ranges: &[IdRange], db_file: &str) -> Result<Vec<TodoView>> {
+    let conn = open_existing(db_file)?;
+    let mut stmt = conn.prepare(&format!("SELECT {TASK_COLUMNS} FROM data WHERE id = ?1"))?;
+
+    let mut tasks = Vec::new();
+    for task_id in expand_ids(ranges) {
+        match stmt
+            .query_row(params![task_id], TodoView::from_row)
+            .optional()?
+        {
+            Some(task) => tasks.push(task),
+            None if ranges
+                .iter()
+                .any(|range| range.first == task_id && range.last == task_id) =>
+            {
+                return Err(TodoError::TaskNotFound(task_id));
+            }
+            None => {}
+        }
+    }
+
+    match ranges.first() {
+        Some(range) if tasks.is_empty() => Err(TodoError::TaskNotFound(range.first)),
+        _ => Ok(tasks),
+    }
+}
+
+/// Gets a single archived task
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if there is no archived task with this id, or an error
+/// if database operations fail
+pub fn get_archived_task(task_id: u64, db_file: &str) -> Result<TodoView> {
+    let conn = open_existing(db_file)?;
+
+    conn.query_row(
+        &format!("SELECT {ARCHIVED_TASK_COLUMNS} FROM archived_data WHERE id = ?1"),
+        params![task_id],
+        TodoView::from_row,
+    )
+    .optional()?
+    .ok_or(TodoError::TaskNotFound(task_id))
+}
+
+/// Completion status a listing should include
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TaskStatus {
+    Pending,
+    Done,
+    #[default]
+    All,
+}
+
+/// Which tasks a listing should include, every filter that is set must match
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct TaskFilter {
+    /// Only tasks whose project matches this glob pattern, e.g. `Work` or `Work*`
+    pub project: Option<String>,
+    /// Only tasks carrying all of these tags
+    pub tags: Vec<String>,
+    /// Match tasks carrying any of `tags` instead of all of them
+    pub any_tag: bool,
+    pub status: TaskStatus,
+    /// Only pending tasks whose due date has passed
+    pub overdue: bool,
+    /// Only tasks due before this date
+    pub due_before: Option<NaiveDate>,
+    /// Only tasks due after this date
+    pub due_after: Option<NaiveDate>,
+    /// Only tasks due today
+    pub due_today: bool,
+    /// Only tasks whose description contains this text, ignoring case
+    pub search: Option<String>,
+    /// Only pending tasks whose prerequisites are all complete
+    pub ready: bool,
+    /// Only tasks added before this date, tasks added before timestamps were kept count as
+    /// the oldest like they sort
+    pub created_before: Option<NaiveDate>,
+    /// Only tasks added after this date
+    pub created_after: Option<NaiveDate>,
+    /// Only tasks completed before this date
+    pub completed_before: Option<NaiveDate>,
+    /// Only tasks completed after this date
+    pub completed_after: Option<NaiveDate>,
+}
+
+impl TaskFilter {
+    /// Compiles the filter into a `WHERE` clause and its positional parameters
+    fn where_clause(&self, today: NaiveDate) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut bind = |value: Value| {
+            values.push(value);
+            format!("?{}", values.len())
+        };
+
+        if let Some(project) = &self.project {
+            conditions.push(format!(
+                "project GLOB {}",
+                bind(Value::Text(project.clone()))
+            ));
+        }
+
+        if !self.tags.is_empty() {
+            let placeholders: Vec<String> = self
+                .tags
+                .iter()
+                .map(|tag| bind(Value::Text(normalise_tag(tag))))
+                .collect();
+
+            let having = if self.any_tag {
+                String::new()
+            } else {
+                format!(
+                    " GROUP BY task_id HAVING COUNT(DISTINCT tags.name) = {}",
+                    placeholders.len()
+                )
+            };
+
+            conditions.push(format!(
+                "id IN (SELECT task_id FROM task_tags JOIN tags ON tags.id = task_tags.tag_id
+                    WHERE tags.name IN ({}){having})",
+                placeholders.join(", ")
+            ));
+        }
+
+        match self.status {
+            TaskStatus::Pending => conditions.push(String::from("complete = 0")),
+            TaskStatus::Done => conditions.push(String::from("complete = 1")),
+            TaskStatus::All => {}
+        }
+
+        if self.overdue {
+            conditions.push(format!(
+                "complete = 0 AND due_date < {}",
+                bind(Value::Text(today.to_string()))
+            ));
+        }
+
+        if let Some(date) = self.due_before {
+            conditions.push(format!(
+                "due_date < {}",
+                bind(Value::Text(date.to_string()))
+            ));
+        }
+
+        if let Some(date) = self.due_after {
+            conditions.push(format!(
+                "due_date > {}",
+                bind(Value::Text(date.to_string()))
+            ));
+        }
+
+        if self.due_today {
+            conditions.push(format!(
+                "due_date = {}",
+                bind(Value::Text(today.to_string()))
+            ));
+        }
+
+        if let Some(text) = &self.search {
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            conditions.push(format!(
+                "task LIKE {} ESCAPE '\\'",
+                bind(Value::Text(format!("%{escaped}%")))
+            ));
+        }
+
+        for (condition, date) in [
+            (
+                "created_at IS NULL OR date(created_at) <",
+                self.created_before,
+            ),
+            ("date(created_at) >", self.created_after),
+            ("date(completed_at) <", self.completed_before),
+            ("date(completed_at) >", self.completed_after),
+        ] {
+            if let Some(date) = date {
+                conditions.push(format!(
+                    "({condition} {})",
+                    bind(Value::Text(date.to_string()))
+                ));
+            }
+        }
+
+        if self.ready {
+            conditions.push(String::from(
+                "complete = 0 AND NOT EXISTS (SELECT 1 FROM task_dependencies
+                    JOIN data AS prerequisite ON prerequisite.id = task_dependencies.depends_on_id
+                    WHERE task_dependencies.task_id = data.id AND prerequisite.complete = 0)",
+            ));
+        }
+
+        let clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        (clause, values)
+    }
+}
+
+/// Which archived tasks a listing should include, every filter that is set must match
+#[derive(Debug, Default)]
+pub struct ArchiveFilter {
+    /// Only tasks whose project matches this glob pattern, e.g. `Work` or `Work*`
+    pub project: Option<String>,
+    /// Only tasks archived before this date
+    pub archived_before: Option<NaiveDate>,
+    /// Only tasks archived after this date
+    pub archived_after: Option<NaiveDate>,
+}
+
+impl ArchiveFilter {
+    /// Compiles the filter into a `WHERE` clause and its positional parameters
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut bind = |value: Value| {
+            values.push(value);
+            format!("?{}", values.len())
+        };
+
+        if let Some(project) = &self.project {
+            conditions.push(format!(
+                "project GLOB {}",
+                bind(Value::Text(project.clone()))
+            ));
+        }
+
+        for (comparison, date) in [("<", self.archived_before), (">", self.archived_after)] {
+            if let Some(date) = date {
+                conditions.push(format!(
+                    "date(archived_date) {comparison} {}",
+                    bind(Value::Text(date.to_string()))
+                ));
+            }
+        }
+
+        let clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        (clause, values)
+    }
+}
+
+/// Columns task listings can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Due,
+    Id,
+    Project,
+    Priority,
+    /// Creation order
+    Created,
+}
+
+impl SortKey {
+    /// `ORDER BY` term in the natural direction, most important and soonest first
+    const fn order_term(self) -> (&'static str, bool) {
+        match self {
+            Self::Due => ("due_date", true),
+            Self::Id => ("id", true),
+            // Tasks from before timestamps were kept count as the oldest
+            Self::Created => ("created_at", true),
+            Self::Project => ("project", true),
+            Self::Priority => ("priority", false),
+        }
+    }
+
+    /// `ORDER BY` term that goes before `order_term` in either direction
+    const fn fixed_term(self) -> Option<&'static str> {
+        match self {
+            // Tasks without a due date go last, even when the order is reversed
+            Self::Due => Some("due_date IS NULL ASC"),
+            _ => None,
+        }
+    }
+}
+
+/// Order of a task listing
+#[derive(Debug)]
+pub struct TaskSort {
+    /// Keys in order of precedence, ties are always broken by id
+    pub keys: Vec<SortKey>,
+    /// Reverse the natural direction of every key
+    pub reverse: bool,
+}
+
+impl Default for TaskSort {
+    fn default() -> Self {
+        Self {
+            keys: vec![SortKey::Priority, SortKey::Due],
+            reverse: false,
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct TodoView {
-    pub id: u64,
-    pub project: String,
-    pub task: String,
-    pub due_date: String,
-    pub complete: bool,
+impl TaskSort {
+    /// Compiles the sort into an `ORDER BY` clause
+    fn order_by_clause(&self) -> String {
+        let terms: Vec<String> = self
+            .keys
+            .iter()
+            .chain(std::iter::once(&SortKey::Id))
+            .flat_map(|key| {
+                let (column, ascending) = key.order_term();
+                let direction = if ascending == self.reverse {
+                    "DESC"
+                } else {
+                    "ASC"
+                };
+
+                key.fixed_term()
+                    .map(String::from)
+                    .into_iter()
+                    .chain(std::iter::once(format!("{column} {direction}")))
+            })
+            .collect();
+
+        format!("ORDER BY {}", terms.join(", "))
+    }
 }
 
-/// Gets tasks for a specific project
+/// Gets the tasks matching a filter in the given order
 ///
 /// # Errors
 ///
 /// Returns an error if database operations fail
-///
-/// # Panics
-///
-/// This function will panic if the database connection cannot be established
-pub fn get_tasks(project_name: &str, db_file: &str) -> Result<Vec<TodoView>> {
-    let conn = Connection::open(db_file).unwrap();
+pub fn query_tasks(filter: &TaskFilter, sort: &TaskSort, db_file: &str) -> Result<Vec<TodoView>> {
+    let conn = open_existing(db_file)?;
 
-    let mut stmt = conn.prepare(
-        "SELECT * FROM data
-                 WHERE project = :project_name OR :project_name IS NULL;",
-    )?;
+    let (where_clause, values) = filter.where_clause(Local::now().date_naive());
+    let order_by_clause = sort.order_by_clause();
 
-    // TODO: Need to match on this to get query binding version
-    let tasks_iter = stmt.query_map(&[(":project_name", &project_name)], |row| {
-        Ok(TodoView {
-            id: row.get(0)?,
-            project: row.get(1)?,
-            task: row.get(2)?,
-            due_date: row.get(3)?,
-            complete: matches!(row.get(4).unwrap(), 1),
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TASK_COLUMNS} FROM data {where_clause} {order_by_clause};"
+    ))?;
+
+    let tasks_iter = stmt.query_map(params_from_iter(values), TodoView::from_row)?;
 
     let mut result = Vec::new();
 
@@ -161,37 +1352,27 @@ pub fn get_tasks(project_name: &str, db_file: &str) -> Result<Vec<TodoView>> {
     Ok(result)
 }
 
-/// Gets all tasks from the database
+/// Gets tasks for a specific project
 ///
 /// # Errors
 ///
 /// Returns an error if database operations fail
+pub fn get_tasks(project_name: &str, db_file: &str) -> Result<Vec<TodoView>> {
+    let filter = TaskFilter {
+        project: Some(project_name.to_owned()),
+        ..TaskFilter::default()
+    };
+
+    query_tasks(&filter, &TaskSort::default(), db_file)
+}
+
+/// Gets all tasks from the database
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if the database connection cannot be established
+/// Returns an error if database operations fail
 pub fn get_all_tasks(db_file: &str) -> Result<Vec<TodoView>> {
-    let conn = Connection::open(db_file).unwrap();
-
-    let mut stmt = conn.prepare("SELECT * FROM data;")?;
-
-    let tasks_iter = stmt.query_map([], |row| {
-        Ok(TodoView {
-            id: row.get(0)?,
-            project: row.get(1)?,
-            task: row.get(2)?,
-            due_date: row.get(3)?,
-            complete: matches!(row.get(4).unwrap(), 1),
-        })
-    })?;
-
-    let mut result = Vec::new();
-
-    for task in tasks_iter {
-        result.push(task?);
-    }
-
-    Ok(result)
+    query_tasks(&TaskFilter::default(), &TaskSort::default(), db_file)
 }
 
 /// Counts pending tasks in the database
@@ -199,12 +1380,8 @@ pub fn get_all_tasks(db_file: &str) -> Result<Vec<TodoView>> {
 /// # Errors
 ///
 /// Returns an error if database operations fail
-///
-/// # Panics
-///
-/// This function will panic if the database connection cannot be established
 pub fn count_pending(db_file: &str) -> Result<u32> {
-    let conn = Connection::open(db_file).unwrap();
+    let conn = open_existing(db_file)?;
 
     let count: u32 = conn.query_row(
         "SELECT COUNT(*) FROM data WHERE complete = 0",
@@ -220,15 +1397,11 @@ pub fn count_pending(db_file: &str) -> Result<u32> {
 /// # Errors
 ///
 /// Returns an error if database operations fail
-///
-/// # Panics
-///
-/// This function will panic if the database connection cannot be established
 pub fn count_overdue(db_file: &str) -> Result<u32> {
-    let conn = Connection::open(db_file).unwrap();
+    let conn = open_existing(db_file)?;
 
     let count: u32 = conn.query_row(
-        "SELECT COUNT(*) FROM data WHERE complete = 0 AND due_date < CURRENT_DATE",
+        "SELECT COUNT(*) FROM data WHERE complete = 0 AND due_date < date('now', 'localtime')",
         params![],
         |row| row.get(0),
     )?;
@@ -236,103 +1409,272 @@ pub fn count_overdue(db_file: &str) -> Result<u32> {
     Ok(count)
 }
 
-/// Archives a task by moving it from the main table to the archive table
+/// Average number of days between adding and completing a task, archived tasks included
+///
+/// Returns `None` when no completed task has both timestamps.
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn average_days_to_complete(db_file: &str) -> Result<Option<f64>> {
+    let conn = open_existing(db_file)?;
+
+    let average = conn.query_row(
+        "SELECT AVG(julianday(completed_at) - julianday(created_at)) FROM (
+            SELECT created_at, completed_at FROM data WHERE complete = 1
+            UNION ALL SELECT created_at, completed_at FROM archived_data WHERE complete = 1
+        )
+        WHERE created_at IS NOT NULL AND completed_at IS NOT NULL",
+        params![],
+        |row| row.get(0),
+    )?;
+
+    Ok(average)
+}
+
+/// Archives a task by moving it from the main table to the archive table, handling its
+/// subtasks as `subtasks` says
+///
+/// Returns the number of subtasks archived along with the task.
 ///
 /// # Errors
 ///
-/// Returns an error if database operations fail or if the task doesn't exist
+/// Returns `TodoError::TaskNotFound` if the task doesn't exist, `TodoError::HasSubtasks` if
+/// it has subtasks and `subtasks` is `Refuse`, or an error if database operations fail
+pub fn archive_task(task_id: u64, subtasks: SubtaskAction, db_file: &str) -> Result<usize> {
+    Ok(archive_tasks(&[task_id], subtasks, db_file)? - 1)
+}
+
+/// Archives several tasks in a single transaction, handling subtasks that aren't in the
+/// list as `subtasks` says
+///
+/// Returns the number of tasks archived, subtasks included.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if the database connection cannot be established
-pub fn archive_task(task_id: u64, db_file: &str) -> Result<()> {
-    let mut conn = Connection::open(db_file).unwrap();
+/// Returns `TodoError::TaskNotFound` if any of the tasks don't exist,
+/// `TodoError::HasSubtasks` if one has subtasks that aren't archived too and `subtasks` is
+/// `Refuse`, or an error if database operations fail. Nothing is archived when an error is
+/// returned.
+pub fn archive_tasks(task_ids: &[u64], subtasks: SubtaskAction, db_file: &str) -> Result<usize> {
+    let mut conn = open_existing(db_file)?;
 
-    // Create archive table if it doesn't exist
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS archived_data (
-            id INTEGER PRIMARY KEY NOT NULL,
-            project VARCHAR(50) NOT NULL,
-            task VARCHAR(100) NOT NULL,
-            due_date DATE,
-            complete BOOLEAN NOT NULL CHECK (complete IN (0, 1)),
-            archived_date DATETIME DEFAULT CURRENT_TIMESTAMP
-        );",
-        (),
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+    let archived = archive_all(&tx, task_ids, subtasks)?;
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+    Ok(archived)
+}
+
+/// Archives several tasks, subtasks first, returning how many were archived
+fn archive_all(tx: &Transaction, task_ids: &[u64], subtasks: SubtaskAction) -> Result<usize> {
+    let mut archived = 0;
+    for task_id in subtasks_first(tx, task_ids)? {
+        // Subtasks archived along with an earlier task are gone already
+        if task_exists(tx, task_id)? {
+            archived += archive_in(tx, task_id, subtasks)?;
+        }
+    }
+
+    Ok(archived)
+}
+
+/// Moves a task and, as `subtasks` says, its subtasks to the archive table, where they
+/// keep their ids
+///
+/// Returns the number of tasks archived, the task itself included.
+fn archive_in(tx: &Transaction, task_id: u64, subtasks: SubtaskAction) -> Result<usize> {
+    let ids = detach_subtasks(tx, task_id, subtasks)?;
+    let placeholders = placeholders(ids.len());
+
+    // Copy the tasks into the archive table
+    let archived = tx.execute(
+        &format!(
+            "INSERT INTO archived_data (id, project, task, due_date, complete, archived_date,
+                priority, tags, parent_id, created_at, updated_at, completed_at, notes)
+            SELECT id, project, task, due_date, complete, {NOW}, priority, tags, parent_id,
+                created_at, updated_at, completed_at, notes
+            FROM (SELECT {TASK_COLUMNS} FROM data WHERE id IN ({placeholders}) ORDER BY id)"
+        ),
+        params_from_iter(&ids),
     )?;
 
+    if archived == 0 {
+        return Err(TodoError::TaskNotFound(task_id));
+    }
+
+    record_removed(tx, &ids, EventAction::Archive)?;
+
+    // Delete from main table
+    tx.execute(
+        &format!("DELETE FROM data WHERE id IN ({placeholders})"),
+        params_from_iter(&ids),
+    )?;
+
+    Ok(archived)
+}
+
+/// Moves an archived task back to the main table with its id, tags and timestamps
+///
+/// It goes back under its parent if the parent is still a task.
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if there is no archived task with this id, or an error
+/// if database operations fail
+pub fn unarchive_task(task_id: u64, db_file: &str) -> Result<()> {
+    let mut conn = open_existing(db_file)?;
+
     let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    let tags: Option<String> = tx
+        .query_row(
+            "SELECT tags FROM archived_data WHERE id = ?1",
+            params![task_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(TodoError::TaskNotFound(task_id))?;
+
+    tx.execute(
+        &format!(
+            "INSERT INTO data (id, project, task, due_date, complete, priority, parent_id,
+                created_at, updated_at, completed_at, notes)
+            SELECT id, project, task, due_date, complete, priority,
+                (SELECT id FROM data WHERE id = archived_data.parent_id),
+                created_at, {NOW}, completed_at, notes
+            FROM archived_data WHERE id = ?1"
+        ),
+        params![task_id],
+    )?;
 
-    // First, get the task data
-    let task_data: Result<(String, String, String, bool), rusqlite::Error> = {
-        let mut stmt =
-            tx.prepare("SELECT project, task, due_date, complete FROM data WHERE id = ?")?;
-        stmt.query_row(params![task_id], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                matches!(row.get::<_, i32>(3)?, 1),
-            ))
-        })
+    let tags: Vec<String> = tags
+        .map(|tags| tags.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    add_tags(&tx, tx.last_insert_rowid(), &tags)?;
+
+    tx.execute("DELETE FROM archived_data WHERE id = ?1", params![task_id])?;
+
+    let state = history::task_state(&tx, task_id)?;
+    history::record(&tx, task_id, EventAction::Restore, None, state.as_ref())?;
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Permanently deletes archived tasks, ids that aren't in the archive are ignored
+///
+/// The history of the tasks is kept.
+///
+/// Returns the number of tasks deleted.
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn purge_archived_tasks(task_ids: &[u64], db_file: &str) -> Result<usize> {
+    let mut conn = open_existing(db_file)?;
+
+    if task_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let tx = conn.transaction()?;
+    let operation = undo::begin_operation(&tx)?;
+
+    for &task_id in task_ids {
+        if let Some(state) = history::archived_task_state(&tx, task_id)? {
+            history::record(&tx, task_id, EventAction::Purge, Some(&state), None)?;
+        }
+    }
+
+    let purged = tx.execute(
+        &format!(
+            "DELETE FROM archived_data WHERE id IN ({})",
+            placeholders(task_ids.len())
+        ),
+        params_from_iter(task_ids),
+    )?;
+
+    undo::finish_operation(&tx, operation)?;
+    tx.commit()?;
+
+    Ok(purged)
+}
+
+/// Turns user input into an FTS5 query that can't be a syntax error
+///
+/// Words are matched as they are, `"quoted phrases"` must appear together and a trailing
+/// `*` matches any word starting with what comes before it, e.g. `rep*` finds "report".
+fn fts_query(input: &str) -> String {
+    let mut terms = Vec::new();
+    let mut push_term = |text: &str, prefix: bool| {
+        if !text.trim().is_empty() {
+            let star = if prefix { "*" } else { "" };
+            terms.push(format!("\"{}\"{star}", text.replace('"', "\"\"")));
+        }
     };
 
-    match task_data {
-        Ok((project, task, due_date, complete)) => {
-            // Insert into archive table
-            tx.execute(
-                "INSERT INTO archived_data (project, task, due_date, complete)
-                VALUES (?1, ?2, ?3, ?4)",
-                params![project, task, due_date, i32::from(complete)],
-            )?;
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        if let Some(phrase) = rest.strip_prefix('"') {
+            let (phrase, after) = phrase.split_once('"').unwrap_or((phrase, ""));
+            let (prefix, after) = after
+                .strip_prefix('*')
+                .map_or((false, after), |after| (true, after));
 
-            // Delete from main table
-            tx.execute("DELETE FROM data WHERE id = ?1", params![task_id])?;
+            push_term(phrase, prefix);
+            rest = after;
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (word, after) = rest.split_at(end);
+            let stem = word.trim_end_matches('*');
 
-            tx.commit()?;
-            Ok(())
+            push_term(stem, stem.len() < word.len());
+            rest = after;
         }
-        Err(_) => Err(rusqlite::Error::QueryReturnedNoRows),
+
+        rest = rest.trim_start();
     }
+
+    terms.join(" ")
 }
 
-/// Gets all archived tasks from the database
+/// Searches task descriptions, notes and project names, best matches first
 ///
 /// # Errors
 ///
 /// Returns an error if database operations fail
-///
-/// # Panics
-///
-/// This function will panic if the database connection cannot be established
-pub fn get_all_archived_tasks(db_file: &str) -> Result<Vec<TodoView>> {
-    let conn = Connection::open(db_file).unwrap();
+pub fn search_tasks(query: &str, include_archived: bool, db_file: &str) -> Result<Vec<TodoView>> {
+    let conn = open_existing(db_file)?;
 
-    // Create archive table if it doesn't exist (in case this is called before archiving anything)
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS archived_data (
-            id INTEGER PRIMARY KEY NOT NULL,
-            project VARCHAR(50) NOT NULL,
-            task VARCHAR(100) NOT NULL,
-            due_date DATE,
-            complete BOOLEAN NOT NULL CHECK (complete IN (0, 1)),
-            archived_date DATETIME DEFAULT CURRENT_TIMESTAMP
-        );",
-        (),
-    )?;
+    let query = fts_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let mut stmt = conn
-        .prepare("SELECT id, project, task, due_date, complete FROM archived_data ORDER BY id;")?;
+    let hits = "SELECT task_id, rank FROM task_search WHERE task_search MATCH ?1 AND archived =";
+    let archived_hits = if include_archived {
+        format!(
+            "UNION ALL SELECT {ARCHIVED_TASK_COLUMNS}, hits.rank AS score
+            FROM ({hits} 1) AS hits JOIN archived_data ON archived_data.id = hits.task_id"
+        )
+    } else {
+        String::new()
+    };
+    let sql = format!(
+        "SELECT {TASK_COLUMNS}, hits.rank AS score
+        FROM ({hits} 0) AS hits JOIN data ON data.id = hits.task_id
+        {archived_hits}
+        ORDER BY score, archived, id;"
+    );
 
-    let tasks_iter = stmt.query_map([], |row| {
-        Ok(TodoView {
-            id: row.get(0)?,
-            project: row.get(1)?,
-            task: row.get(2)?,
-            due_date: row.get(3)?,
-            complete: matches!(row.get(4).unwrap(), 1),
-        })
-    })?;
+    let mut stmt = conn.prepare(&sql)?;
+    let tasks_iter = stmt.query_map(params![query], TodoView::from_row)?;
 
     let mut result = Vec::new();
 
@@ -343,45 +1685,29 @@ pub fn get_all_archived_tasks(db_file: &str) -> Result<Vec<TodoView>> {
     Ok(result)
 }
 
-/// Gets archived tasks for a specific project
+/// Gets all archived tasks from the database
 ///
 /// # Errors
 ///
 /// Returns an error if database operations fail
+pub fn get_all_archived_tasks(db_file: &str) -> Result<Vec<TodoView>> {
+    get_archived_tasks(&ArchiveFilter::default(), db_file)
+}
+
+/// Gets the archived tasks matching a filter, in id order
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if the database connection cannot be established
-pub fn get_archived_tasks(project_name: &str, db_file: &str) -> Result<Vec<TodoView>> {
-    let conn = Connection::open(db_file).unwrap();
-
-    // Create archive table if it doesn't exist (in case this is called before archiving anything)
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS archived_data (
-            id INTEGER PRIMARY KEY NOT NULL,
-            project VARCHAR(50) NOT NULL,
-            task VARCHAR(100) NOT NULL,
-            due_date DATE,
-            complete BOOLEAN NOT NULL CHECK (complete IN (0, 1)),
-            archived_date DATETIME DEFAULT CURRENT_TIMESTAMP
-        );",
-        (),
-    )?;
+/// Returns an error if database operations fail
+pub fn get_archived_tasks(filter: &ArchiveFilter, db_file: &str) -> Result<Vec<TodoView>> {
+    let conn = open_existing(db_file)?;
 
-    let mut stmt = conn.prepare(
-        "SELECT id, project, task, due_date, complete FROM archived_data
-         WHERE project = :project_name ORDER BY id;",
-    )?;
+    let (where_clause, values) = filter.where_clause();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ARCHIVED_TASK_COLUMNS} FROM archived_data {where_clause} ORDER BY id;"
+    ))?;
 
-    let tasks_iter = stmt.query_map(&[(":project_name", &project_name)], |row| {
-        Ok(TodoView {
-            id: row.get(0)?,
-            project: row.get(1)?,
-            task: row.get(2)?,
-            due_date: row.get(3)?,
-            complete: matches!(row.get(4).unwrap(), 1),
-        })
-    })?;
+    let tasks_iter = stmt.query_map(params_from_iter(values), TodoView::from_row)?;
 
     let mut result = Vec::new();
 
@@ -392,335 +1718,1509 @@ pub fn get_archived_tasks(project_name: &str, db_file: &str) -> Result<Vec<TodoV
     Ok(result)
 }
 
+/// Gives each test its own empty database so tests can run in parallel
+#[cfg(test)]
+pub(super) fn test_database(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("todo-test-{name}.db"));
+    let _ = std::fs::remove_file(&path);
+
+    path.to_str().unwrap().to_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    static TEST_DATABASE: &str = "test.db";
-
-    fn drop_table() -> Result<()> {
-        let conn = Connection::open(TEST_DATABASE).unwrap();
-        conn.execute("DROP TABLE IF EXISTS data;", ())?;
-        conn.execute("DROP TABLE IF EXISTS archived_data;", ())?;
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
 
-        Ok(())
+    /// Clears the timestamps, which depend on when the test runs
+    fn untimed(tasks: Vec<TodoView>) -> Vec<TodoView> {
+        tasks
+            .into_iter()
+            .map(|task| TodoView {
+                created_at: None,
+                updated_at: None,
+                completed_at: None,
+                archived_at: None,
+                ..task
+            })
+            .collect()
     }
 
     #[test]
     fn add_data() {
+        let test_db = test_database("add_data");
+
         let sample = TodoData {
             project: String::from("Test"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
-        assert_eq!(Ok(()), sample.write_data(TEST_DATABASE));
-
-        drop_table().unwrap();
+        sample.write_data(&test_db).unwrap();
     }
 
     #[test]
     fn update_data() {
-        drop_table().unwrap();
+        let test_db = test_database("update_data");
 
         let prepare = TodoData {
             project: String::from("Test"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
-        let sample = TodoData {
+        prepare
+            .write_data(&test_db)
+            .expect("database does not exist");
+
+        let changes = TaskChanges {
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+
+        update_task(1, &changes, &test_db).unwrap();
+        assert!(get_all_tasks(&test_db).unwrap()[0].complete);
+    }
+
+    #[test]
+    fn update_only_supplied_fields() {
+        let test_db = test_database("update_only_supplied_fields");
+
+        let prepare = TodoData {
+            project: String::from("Test"),
+            task: String::from("Original"),
+            due_date: date("2023-01-01"),
+            complete: false,
+            ..TodoData::default()
+        };
+
+        prepare
+            .write_data(&test_db)
+            .expect("database does not exist");
+
+        let changes = TaskChanges {
+            task: Some(String::from("Reworded")),
+            due_date: Some(date("2023-02-01")),
+            ..TaskChanges::default()
+        };
+
+        update_task(1, &changes, &test_db).unwrap();
+
+        assert_eq!(
+            vec![TodoView {
+                id: 1,
+                project: String::from("Test"),
+                task: String::from("Reworded"),
+                due_date: Some(date("2023-02-01")),
+                complete: false,
+                ..TodoView::default()
+            }],
+            untimed(get_all_tasks(&test_db).unwrap())
+        );
+    }
+
+    #[test]
+    fn update_missing_task_reports_not_found() {
+        let test_db = test_database("update_missing_task_reports_not_found");
+
+        let prepare = TodoData {
             project: String::from("Test"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         prepare
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("database does not exist");
 
-        let sample_param = UpdateTask {
-            id: 1,
-            complete: true,
-            delete: false,
+        let changes = TaskChanges {
+            project: Some(String::from("Elsewhere")),
+            ..TaskChanges::default()
         };
 
-        assert_eq!(Ok(()), sample.update_task(sample_param, TEST_DATABASE));
+        assert!(matches!(
+            update_task(42, &changes, &test_db),
+            Err(TodoError::TaskNotFound(42))
+        ));
     }
 
     #[test]
     fn delete_data() {
-        drop_table().unwrap();
+        let test_db = test_database("delete_data");
 
         let prepare = TodoData {
             project: String::from("Test"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
-        let sample = TodoData {
+        prepare
+            .write_data(&test_db)
+            .expect("database does not exist");
+
+        delete_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+        assert!(get_all_tasks(&test_db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delete_missing_task_reports_not_found() {
+        let test_db = test_database("delete_missing_task_reports_not_found");
+
+        let prepare = TodoData {
             project: String::from("Test"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
+            complete: false,
+            ..TodoData::default()
+        };
+
+        prepare
+            .write_data(&test_db)
+            .expect("database does not exist");
+
+        assert!(matches!(
+            delete_task(42, SubtaskAction::Refuse, &test_db),
+            Err(TodoError::TaskNotFound(42))
+        ));
+        assert_eq!(1, get_all_tasks(&test_db).unwrap().len());
+    }
+
+    #[test]
+    fn get_single_task() {
+        let test_db = test_database("get_single_task");
+
+        let prepare = TodoData {
+            project: String::from("Apple"),
+            task: String::from("Test"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         prepare
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("database does not exist");
 
-        let sample_param = UpdateTask {
-            id: 1,
+        assert_eq!("Test", get_task(1, &test_db).unwrap().task);
+        assert!(matches!(
+            get_task(2, &test_db),
+            Err(TodoError::TaskNotFound(2))
+        ));
+    }
+
+    #[test]
+    fn get_all_data() {
+        let test_db = test_database("get_all_data");
+
+        let prepare = TodoData {
+            project: String::from("Apple"),
+            task: String::from("Test"),
+            due_date: date("2023-01-01"),
             complete: false,
-            delete: true,
+            ..TodoData::default()
+        };
+
+        prepare
+            .write_data(&test_db)
+            .expect("database does not exist");
+
+        assert_eq!(
+            vec![TodoView {
+                id: 1,
+                project: String::from("Apple"),
+                task: String::from("Test"),
+                due_date: Some(date("2023-01-01")),
+                complete: false,
+                ..TodoView::default()
+            }],
+            untimed(get_all_tasks(&test_db).unwrap())
+        );
+    }
+
+    #[test]
+    fn tasks_are_ordered_by_priority_then_due_date() {
+        let test_db = test_database("tasks_are_ordered_by_priority_then_due_date");
+
+        for (task, due_date, priority) in [
+            ("Later", "2023-02-01", Priority::High),
+            ("Minor", "2023-01-01", Priority::Low),
+            ("Sooner", "2023-01-15", Priority::High),
+            ("Fire", "2023-03-01", Priority::Urgent),
+        ] {
+            TodoData {
+                project: String::from("Apple"),
+                task: String::from(task),
+                due_date: date(due_date),
+                priority,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let order: Vec<String> = get_all_tasks(&test_db)
+            .unwrap()
+            .into_iter()
+            .map(|task| task.task)
+            .collect();
+        assert_eq!(vec!["Fire", "Sooner", "Later", "Minor"], order);
+    }
+
+    #[test]
+    fn sort_by_several_keys() {
+        let test_db = test_database("sort_by_several_keys");
+
+        for (project, task, due_date) in [
+            ("Work", "Work later", "2023-02-01"),
+            ("Home", "Home", "2023-03-01"),
+            ("Work", "Work sooner", "2023-01-01"),
+        ] {
+            TodoData {
+                project: String::from(project),
+                task: String::from(task),
+                due_date: date(due_date),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let names = |sort: TaskSort| -> Vec<String> {
+            query_tasks(&TaskFilter::default(), &sort, &test_db)
+                .unwrap()
+                .into_iter()
+                .map(|task| task.task)
+                .collect()
         };
 
-        assert_eq!(Ok(()), sample.update_task(sample_param, TEST_DATABASE));
+        let by_project = TaskSort {
+            keys: vec![SortKey::Project, SortKey::Due],
+            reverse: false,
+        };
+        assert_eq!(vec!["Home", "Work sooner", "Work later"], names(by_project));
+
+        // Tasks without a due date stay last either way
+        TodoData {
+            project: String::from("Home"),
+            task: String::from("Someday"),
+            due_date: date("2023-01-01"),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+        open(&test_db)
+            .unwrap()
+            .execute("UPDATE data SET due_date = NULL WHERE id = 4", params![])
+            .unwrap();
+
+        let due = |reverse| TaskSort {
+            keys: vec![SortKey::Due],
+            reverse,
+        };
+        assert_eq!(
+            vec!["Work sooner", "Work later", "Home", "Someday"],
+            names(due(false))
+        );
+        assert_eq!(
+            vec!["Home", "Work later", "Work sooner", "Someday"],
+            names(due(true))
+        );
     }
 
     #[test]
-    fn get_all_data() {
-        drop_table().unwrap();
+    fn get_project_data() {
+        let test_db = test_database("get_project_data");
 
         let prepare = TodoData {
             project: String::from("Apple"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         prepare
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("database does not exist");
 
         assert_eq!(
-            Ok(vec![TodoView {
+            vec![TodoView {
                 id: 1,
                 project: String::from("Apple"),
                 task: String::from("Test"),
-                due_date: String::from("2023-01-01"),
+                due_date: Some(date("2023-01-01")),
                 complete: false,
-            }]),
-            get_all_tasks(TEST_DATABASE)
+                ..TodoView::default()
+            }],
+            untimed(get_tasks("Apple", &test_db).unwrap())
         );
     }
 
     #[test]
-    fn get_project_data() {
-        drop_table().unwrap();
+    fn malformed_due_date_reads_as_none() {
+        let test_db = test_database("malformed_due_date_reads_as_none");
+
+        open(&test_db)
+            .unwrap()
+            .execute(
+                "INSERT INTO data (project, task, due_date, complete)
+                VALUES ('Legacy', 'Bad date', 'someday', 0)",
+                (),
+            )
+            .unwrap();
+
+        assert_eq!(None, get_all_tasks(&test_db).unwrap()[0].due_date);
+    }
 
-        let prepare = TodoData {
-            project: String::from("Apple"),
-            task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
-            complete: false,
+    #[test]
+    fn inline_tags_are_extracted() {
+        assert_eq!(
+            (
+                String::from("buy apples for +3 people"),
+                vec![String::from("food"), String::from("Home")]
+            ),
+            extract_inline_tags("buy +food apples for +3 people +Home")
+        );
+    }
+
+    #[test]
+    fn filter_by_tags() {
+        let test_db = test_database("filter_by_tags");
+
+        for (task, tags) in [
+            ("Both", vec!["work", "urgent"]),
+            ("Work only", vec!["Work"]),
+            ("Untagged", vec![]),
+        ] {
+            TodoData {
+                project: String::from("Apple"),
+                task: String::from(task),
+                due_date: date("2023-01-01"),
+                tags: tags.into_iter().map(String::from).collect(),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let names = |filter: &TaskFilter| -> Vec<String> {
+            query_tasks(filter, &TaskSort::default(), &test_db)
+                .unwrap()
+                .into_iter()
+                .map(|task| task.task)
+                .collect()
         };
 
-        prepare
-            .write_data(TEST_DATABASE)
+        let all_of = TaskFilter {
+            tags: vec![String::from("work"), String::from("urgent")],
+            ..TaskFilter::default()
+        };
+        assert_eq!(vec!["Both"], names(&all_of));
+
+        let any_of = TaskFilter {
+            any_tag: true,
+            ..all_of
+        };
+        assert_eq!(vec!["Both", "Work only"], names(&any_of));
+
+        assert_eq!(
+            vec![String::from("urgent"), String::from("work")],
+            get_task(1, &test_db).unwrap().tags
+        );
+    }
+
+    #[test]
+    fn filters_combine() {
+        let test_db = test_database("filters_combine");
+        let today = Local::now().date_naive();
+
+        for (project, task, due_date, complete) in [
+            ("Work", "Past report", today - chrono::Days::new(3), false),
+            (
+                "Work/Sprint",
+                "Done review",
+                today - chrono::Days::new(1),
+                true,
+            ),
+            ("Workshop", "Today 100% effort", today, false),
+            ("Home", "Future chores", today + chrono::Days::new(5), false),
+        ] {
+            TodoData {
+                project: String::from(project),
+                task: String::from(task),
+                due_date,
+                complete,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
             .expect("database does not exist");
+        }
+
+        let names = |filter: TaskFilter| -> Vec<String> {
+            let mut names: Vec<String> = query_tasks(&filter, &TaskSort::default(), &test_db)
+                .unwrap()
+                .into_iter()
+                .map(|task| task.task)
+                .collect();
+            names.sort();
+            names
+        };
 
         assert_eq!(
-            Ok(vec![TodoView {
-                id: 1,
-                project: String::from("Apple"),
-                task: String::from("Test"),
-                due_date: String::from("2023-01-01"),
-                complete: false,
-            }]),
-            get_tasks("Apple", TEST_DATABASE)
+            vec!["Past report"],
+            names(TaskFilter {
+                overdue: true,
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Done review"],
+            names(TaskFilter {
+                status: TaskStatus::Done,
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Today 100% effort"],
+            names(TaskFilter {
+                due_today: true,
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Done review", "Past report", "Today 100% effort"],
+            names(TaskFilter {
+                project: Some(String::from("Work*")),
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Past report"],
+            names(TaskFilter {
+                project: Some(String::from("Work")),
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Done review", "Today 100% effort"],
+            names(TaskFilter {
+                due_after: Some(today - chrono::Days::new(2)),
+                due_before: Some(today + chrono::Days::new(1)),
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec!["Today 100% effort"],
+            names(TaskFilter {
+                search: Some(String::from("100%")),
+                status: TaskStatus::Pending,
+                ..TaskFilter::default()
+            })
+        );
+        assert!(names(TaskFilter {
+            search: Some(String::from("0%e")),
+            ..TaskFilter::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn archived_tasks_keep_their_tags() {
+        let test_db = test_database("archived_tasks_keep_their_tags");
+
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Tagged"),
+            due_date: date("2023-01-01"),
+            tags: vec![String::from("report")],
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("Failed to create task");
+
+        archive_task(1, SubtaskAction::Refuse, &test_db).expect("Failed to archive task");
+
+        assert_eq!(
+            vec![String::from("report")],
+            get_all_archived_tasks(&test_db).unwrap()[0].tags
         );
     }
 
     #[test]
     fn get_pending_count() {
-        drop_table().unwrap();
+        let test_db = test_database("get_pending_count");
 
         let prepare = TodoData {
             project: String::from("Apple"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         prepare
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("database does not exist");
 
-        assert_eq!(Ok(1), count_pending(TEST_DATABASE));
+        assert_eq!(1, count_pending(&test_db).unwrap());
     }
 
     #[test]
     fn get_overdue_count() {
-        drop_table().unwrap();
+        let test_db = test_database("get_overdue_count");
 
         let prepare = TodoData {
             project: String::from("Apple"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         prepare
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("database does not exist");
 
-        assert_eq!(Ok(1), count_overdue(TEST_DATABASE));
+        assert_eq!(1, count_overdue(&test_db).unwrap());
     }
 
     #[test]
     fn test_archive_task() {
-        drop_table().unwrap();
+        let test_db = test_database("test_archive_task");
 
         // Create a task first
         let task = TodoData {
             project: String::from("Work"),
             task: String::from("Complete presentation"),
-            due_date: String::from("2023-01-15"),
+            due_date: date("2023-01-15"),
             complete: false,
+            ..TodoData::default()
         };
 
-        task.write_data(TEST_DATABASE)
-            .expect("Failed to create task");
+        task.write_data(&test_db).expect("Failed to create task");
 
         // Archive the task
-        assert_eq!(Ok(()), archive_task(1, TEST_DATABASE));
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
 
         // Verify task is removed from main table
-        let main_tasks = get_all_tasks(TEST_DATABASE).unwrap();
+        let main_tasks = get_all_tasks(&test_db).unwrap();
         assert_eq!(main_tasks.len(), 0);
 
         // Verify task is in archive table
-        let archived_tasks = get_all_archived_tasks(TEST_DATABASE).unwrap();
+        let archived_tasks = get_all_archived_tasks(&test_db).unwrap();
         assert_eq!(archived_tasks.len(), 1);
         assert_eq!(archived_tasks[0].project, "Work");
         assert_eq!(archived_tasks[0].task, "Complete presentation");
-
-        drop_table().unwrap();
     }
 
     #[test]
     fn archive_nonexistent_task() {
-        drop_table().unwrap();
+        let test_db = test_database("archive_nonexistent_task");
 
         // Try to archive a task that doesn't exist
-        let result = archive_task(999, TEST_DATABASE);
+        let result = archive_task(999, SubtaskAction::Refuse, &test_db);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn archive_missing_task_reports_not_found() {
+        let test_db = test_database("archive_missing_task_reports_not_found");
+
+        let task = TodoData {
+            project: String::from("Work"),
+            task: String::from("Only task"),
+            due_date: date("2023-01-01"),
+            complete: false,
+            ..TodoData::default()
+        };
+        task.write_data(&test_db).expect("Failed to create task");
+
+        let result = archive_task(42, SubtaskAction::Refuse, &test_db);
+        assert!(matches!(result, Err(TodoError::TaskNotFound(42))));
+    }
+
     #[test]
     fn test_get_all_archived_tasks() {
-        drop_table().unwrap();
+        let test_db = test_database("test_get_all_archived_tasks");
 
         // Create and archive multiple tasks
         let task1 = TodoData {
             project: String::from("Work"),
             task: String::from("Task 1"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: true,
+            ..TodoData::default()
         };
 
         let task2 = TodoData {
             project: String::from("Personal"),
             task: String::from("Task 2"),
-            due_date: String::from("2023-01-02"),
+            due_date: date("2023-01-02"),
             complete: false,
+            ..TodoData::default()
         };
 
-        task1
-            .write_data(TEST_DATABASE)
-            .expect("Failed to create task1");
-        task2
-            .write_data(TEST_DATABASE)
-            .expect("Failed to create task2");
+        task1.write_data(&test_db).expect("Failed to create task1");
+        task2.write_data(&test_db).expect("Failed to create task2");
 
         // Archive both tasks
-        archive_task(1, TEST_DATABASE).expect("Failed to archive task1");
-        archive_task(2, TEST_DATABASE).expect("Failed to archive task2");
+        archive_task(1, SubtaskAction::Refuse, &test_db).expect("Failed to archive task1");
+        archive_task(2, SubtaskAction::Refuse, &test_db).expect("Failed to archive task2");
 
         // Get all archived tasks
-        let archived = get_all_archived_tasks(TEST_DATABASE).unwrap();
+        let archived = get_all_archived_tasks(&test_db).unwrap();
         assert_eq!(archived.len(), 2);
 
         // Verify main table is empty
-        let main_tasks = get_all_tasks(TEST_DATABASE).unwrap();
+        let main_tasks = get_all_tasks(&test_db).unwrap();
         assert_eq!(main_tasks.len(), 0);
-
-        drop_table().unwrap();
     }
 
     #[test]
     fn get_archived_tasks_by_project() {
-        drop_table().unwrap();
+        let test_db = test_database("get_archived_tasks_by_project");
 
         // Create tasks in different projects
         let work_task = TodoData {
             project: String::from("Work"),
             task: String::from("Work task"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
         let personal_task = TodoData {
             project: String::from("Personal"),
             task: String::from("Personal task"),
-            due_date: String::from("2023-01-02"),
+            due_date: date("2023-01-02"),
             complete: false,
+            ..TodoData::default()
         };
 
         work_task
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("Failed to create work task");
         personal_task
-            .write_data(TEST_DATABASE)
+            .write_data(&test_db)
             .expect("Failed to create personal task");
 
         // Archive both tasks
-        archive_task(1, TEST_DATABASE).expect("Failed to archive work task");
-        archive_task(2, TEST_DATABASE).expect("Failed to archive personal task");
+        archive_task(1, SubtaskAction::Refuse, &test_db).expect("Failed to archive work task");
+        archive_task(2, SubtaskAction::Refuse, &test_db).expect("Failed to archive personal task");
 
         // Get archived tasks for Work project only
-        let work_archived = get_archived_tasks("Work", TEST_DATABASE).unwrap();
+        let work = ArchiveFilter {
+            project: Some(String::from("Work")),
+            ..ArchiveFilter::default()
+        };
+        let work_archived = get_archived_tasks(&work, &test_db).unwrap();
         assert_eq!(work_archived.len(), 1);
         assert_eq!(work_archived[0].project, "Work");
         assert_eq!(work_archived[0].task, "Work task");
 
         // Get archived tasks for Personal project only
-        let personal_archived = get_archived_tasks("Personal", TEST_DATABASE).unwrap();
+        let personal = ArchiveFilter {
+            project: Some(String::from("Personal")),
+            ..ArchiveFilter::default()
+        };
+        let personal_archived = get_archived_tasks(&personal, &test_db).unwrap();
         assert_eq!(personal_archived.len(), 1);
         assert_eq!(personal_archived[0].project, "Personal");
         assert_eq!(personal_archived[0].task, "Personal task");
 
-        drop_table().unwrap();
+        // Projects match glob patterns like in task listings
+        let pattern = ArchiveFilter {
+            project: Some(String::from("P*")),
+            ..ArchiveFilter::default()
+        };
+        assert_eq!(
+            vec![2],
+            get_archived_tasks(&pattern, &test_db)
+                .unwrap()
+                .iter()
+                .map(|task| task.id)
+                .collect::<Vec<_>>()
+        );
+
+        let today = Local::now().date_naive();
+        let archived_between = |after: NaiveDate, before: NaiveDate| {
+            let filter = ArchiveFilter {
+                archived_after: Some(after),
+                archived_before: Some(before),
+                ..ArchiveFilter::default()
+            };
+            get_archived_tasks(&filter, &test_db).unwrap().len()
+        };
+        assert_eq!(
+            2,
+            archived_between(today.pred_opt().unwrap(), today.succ_opt().unwrap())
+        );
+        assert_eq!(0, archived_between(date("2000-01-01"), today));
     }
 
     #[test]
     fn archive_task_transaction_integrity() {
-        drop_table().unwrap();
+        let test_db = test_database("archive_task_transaction_integrity");
 
         // Create a task
         let task = TodoData {
             project: String::from("Test"),
             task: String::from("Transaction test"),
-            due_date: String::from("2023-01-01"),
+            due_date: date("2023-01-01"),
             complete: false,
+            ..TodoData::default()
         };
 
-        task.write_data(TEST_DATABASE)
-            .expect("Failed to create task");
+        task.write_data(&test_db).expect("Failed to create task");
 
         // Archive the task
-        archive_task(1, TEST_DATABASE).expect("Failed to archive task");
+        archive_task(1, SubtaskAction::Refuse, &test_db).expect("Failed to archive task");
 
         // Verify exactly one task in archive, zero in main
-        let main_count = get_all_tasks(TEST_DATABASE).unwrap().len();
-        let archive_count = get_all_archived_tasks(TEST_DATABASE).unwrap().len();
+        let main_count = get_all_tasks(&test_db).unwrap().len();
+        let archive_count = get_all_archived_tasks(&test_db).unwrap().len();
 
         assert_eq!(main_count, 0, "Task should be removed from main table");
         assert_eq!(archive_count, 1, "Task should exist in archive table");
     }
+
+    #[test]
+    fn search_queries_are_escaped() {
+        assert_eq!("\"buy\" \"apples\"", fts_query("buy  apples"));
+        assert_eq!("\"rep\"* \"q3 report\"", fts_query("rep* \"q3 report\""));
+        assert_eq!("\"big proj\"*", fts_query("\"big proj\"*"));
+        assert_eq!("\"don't\" \"a\"\"b\"", fts_query("don't a\"b"));
+        assert_eq!("\"open\"", fts_query("\"open"));
+        assert_eq!("", fts_query(" * \"\" "));
+    }
+
+    #[test]
+    fn search_ranks_and_matches_phrases_and_prefixes() {
+        let test_db = test_database("search_ranks_and_matches_phrases_and_prefixes");
+
+        for (project, task) in [
+            ("Home", "Write shopping list"),
+            ("Work", "Write quarterly report"),
+            ("Report", "Send invoices"),
+            ("Work", "Report quarterly numbers to the board"),
+        ] {
+            TodoData {
+                project: String::from(project),
+                task: String::from(task),
+                due_date: date("2023-01-01"),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let ids = |query: &str| -> Vec<u64> {
+            search_tasks(query, false, &test_db)
+                .unwrap()
+                .into_iter()
+                .map(|task| task.id)
+                .collect()
+        };
+
+        // Matches in the description rank above matches in the project name
+        let report = ids("report");
+        assert_eq!(3, report.len());
+        assert_eq!(3, report[2]);
+        assert_eq!(vec![2], ids("\"quarterly report\""));
+        assert_eq!(vec![2], ids("quart* write*"));
+        assert_eq!(vec![1], ids("shop*"));
+        assert!(ids("shop").is_empty());
+        assert!(ids("\"\"").is_empty());
+    }
+
+    #[test]
+    fn search_index_follows_changes_and_archive() {
+        let test_db = test_database("search_index_follows_changes_and_archive");
+
+        for task in ["Paint the fence", "Fix the gate"] {
+            TodoData {
+                project: String::from("Garden"),
+                task: String::from(task),
+                due_date: date("2023-01-01"),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let changes = TaskChanges {
+            task: Some(String::from("Paint the shed")),
+            ..TaskChanges::default()
+        };
+        update_task(1, &changes, &test_db).unwrap();
+        assert!(search_tasks("fence", false, &test_db).unwrap().is_empty());
+        assert_eq!(1, search_tasks("shed", false, &test_db).unwrap()[0].id);
+
+        archive_task(2, SubtaskAction::Refuse, &test_db).unwrap();
+        assert!(search_tasks("gate", false, &test_db).unwrap().is_empty());
+
+        let archived = search_tasks("gate", true, &test_db).unwrap();
+        assert_eq!(1, archived.len());
+        assert!(archived[0].archived);
+        assert_eq!("Fix the gate", archived[0].task);
+
+        delete_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+        assert!(search_tasks("paint", true, &test_db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn notes_are_searchable_and_follow_the_task() {
+        let test_db = test_database("notes_are_searchable_and_follow_the_task");
+
+        TodoData {
+            project: String::from("Home"),
+            task: String::from("Fix the sink"),
+            due_date: date("2024-06-14"),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+
+        let notes = |text: &str| TaskChanges {
+            notes: Some(String::from(text)),
+            ..TaskChanges::default()
+        };
+        update_task(1, &notes("Washer is worn\nBuy a 15mm one"), &test_db).unwrap();
+        assert_eq!(
+            Some("Washer is worn\nBuy a 15mm one"),
+            get_task(1, &test_db).unwrap().notes.as_deref()
+        );
+        assert_eq!(1, search_tasks("washer", false, &test_db).unwrap()[0].id);
+
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+        let archived = get_archived_task(1, &test_db).unwrap();
+        assert_eq!(
+            Some("Washer is worn\nBuy a 15mm one"),
+            archived.notes.as_deref()
+        );
+        assert!(search_tasks("washer", false, &test_db).unwrap().is_empty());
+        assert_eq!(1, search_tasks("15mm", true, &test_db).unwrap().len());
+
+        unarchive_task(1, &test_db).unwrap();
+        update_task(1, &notes(""), &test_db).unwrap();
+        assert_eq!(None, get_task(1, &test_db).unwrap().notes);
+        assert!(search_tasks("washer", true, &test_db).unwrap().is_empty());
+
+        undo::undo(1, &test_db).unwrap();
+        assert_eq!(
+            Some("Washer is worn\nBuy a 15mm one"),
+            get_task(1, &test_db).unwrap().notes.as_deref()
+        );
+    }
+
+    #[test]
+    fn completing_recurring_task_adds_next_occurrence() {
+        let test_db = test_database("completing_recurring_task_adds_next_occurrence");
+
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Standup notes"),
+            due_date: date("2999-01-01"),
+            priority: Priority::High,
+            tags: vec![String::from("team")],
+            repeat: Some(crate::recurrence::parse_recurrence("weekly").unwrap()),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+
+        let complete = TaskChanges {
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+        assert_eq!(Some(2), update_task(1, &complete, &test_db).unwrap());
+
+        let next = get_task(2, &test_db).unwrap();
+        assert!(next.created_at.is_some());
+        assert_eq!(
+            TodoView {
+                id: 2,
+                project: String::from("Work"),
+                task: String::from("Standup notes"),
+                due_date: Some(date("2999-01-08")),
+                complete: false,
+                priority: Priority::High,
+                tags: vec![String::from("team")],
+                ..TodoView::default()
+            },
+            untimed(vec![next]).remove(0)
+        );
+
+        // Completing an already complete task doesn't add another occurrence
+        assert_eq!(None, update_task(1, &complete, &test_db).unwrap());
+        assert_eq!(2, get_all_tasks(&test_db).unwrap().len());
+
+        let series = get_series(&test_db).unwrap();
+        assert_eq!(1, series.len());
+        assert_eq!(Some(2), series[0].task_id);
+    }
+
+    #[test]
+    fn paused_and_ended_series_stop_repeating() {
+        let test_db = test_database("paused_and_ended_series_stop_repeating");
+
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Monthly report"),
+            due_date: date("2999-01-31"),
+            repeat: Some(crate::recurrence::parse_recurrence("monthly").unwrap()),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+
+        let complete = TaskChanges {
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+
+        assert_eq!(None, set_series_paused(1, true, &test_db).unwrap());
+        assert_eq!(None, update_task(1, &complete, &test_db).unwrap());
+        assert!(get_series(&test_db).unwrap()[0].paused);
+
+        // Resuming catches up on the occurrence skipped while paused
+        assert_eq!(Some(2), set_series_paused(1, false, &test_db).unwrap());
+        assert_eq!(
+            Some(date("2999-02-28")),
+            get_task(2, &test_db).unwrap().due_date
+        );
+
+        end_series(1, &test_db).unwrap();
+        assert_eq!(None, update_task(2, &complete, &test_db).unwrap());
+        assert!(get_series(&test_db).unwrap().is_empty());
+        assert!(matches!(
+            end_series(1, &test_db),
+            Err(TodoError::SeriesNotFound(1))
+        ));
+    }
+
+    #[test]
+    fn subtasks_roll_up_and_follow_their_parent() {
+        let test_db = test_database("subtasks_roll_up_and_follow_their_parent");
+
+        for (task, parent_id, complete) in [
+            ("Plan trip", None, false),
+            ("Book flights", Some(1), true),
+            ("Book hotel", Some(1), false),
+            ("Pick hotel area", Some(3), false),
+        ] {
+            TodoData {
+                project: String::from("Travel"),
+                task: String::from(task),
+                due_date: date("2023-01-01"),
+                complete,
+                parent_id,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let parent = get_task(1, &test_db).unwrap();
+        assert_eq!((1, 2), (parent.subtasks_done, parent.subtasks_total));
+        assert_eq!(Some(3), get_task(4, &test_db).unwrap().parent_id);
+
+        let orphan = TodoData {
+            task: String::from("Orphan"),
+            parent_id: Some(42),
+            ..TodoData::default()
+        };
+        assert!(matches!(
+            orphan.write_data(&test_db),
+            Err(TodoError::TaskNotFound(42))
+        ));
+
+        assert!(matches!(
+            delete_task(1, SubtaskAction::Refuse, &test_db),
+            Err(TodoError::HasSubtasks(1))
+        ));
+        assert_eq!(4, get_all_tasks(&test_db).unwrap().len());
+
+        // Reparenting moves the subtasks of task 3 up to task 1
+        assert_eq!(
+            0,
+            delete_task(3, SubtaskAction::Reparent, &test_db).unwrap()
+        );
+        assert_eq!(Some(1), get_task(4, &test_db).unwrap().parent_id);
+
+        assert_eq!(
+            2,
+            archive_task(1, SubtaskAction::Cascade, &test_db).unwrap()
+        );
+        assert!(get_all_tasks(&test_db).unwrap().is_empty());
+
+        let archived = get_all_archived_tasks(&test_db).unwrap();
+        assert_eq!(3, archived.len());
+        assert_eq!(Some(1), archived[1].parent_id);
+    }
+
+    #[test]
+    fn dependencies_block_tasks_until_complete() {
+        let test_db = test_database("dependencies_block_tasks_until_complete");
+
+        for task in ["Design", "Build", "Ship"] {
+            TodoData {
+                project: String::from("Work"),
+                task: String::from(task),
+                due_date: date("2023-01-01"),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        add_dependencies(2, &[1], &test_db).unwrap();
+        add_dependencies(3, &[1, 2], &test_db).unwrap();
+        assert_eq!(vec![1, 2], get_task(3, &test_db).unwrap().blocked_by);
+
+        assert!(matches!(
+            add_dependencies(1, &[3], &test_db),
+            Err(TodoError::DependencyCycle(1, 3))
+        ));
+        assert!(matches!(
+            add_dependencies(1, &[1], &test_db),
+            Err(TodoError::DependencyCycle(1, 1))
+        ));
+        assert!(matches!(
+            add_dependencies(1, &[42], &test_db),
+            Err(TodoError::TaskNotFound(42))
+        ));
+
+        let ready = |test_db: &str| -> Vec<u64> {
+            let filter = TaskFilter {
+                ready: true,
+                ..TaskFilter::default()
+            };
+            query_tasks(&filter, &TaskSort::default(), test_db)
+                .unwrap()
+                .into_iter()
+                .map(|task| task.id)
+                .collect()
+        };
+        assert_eq!(vec![1], ready(&test_db));
+
+        let complete = TaskChanges {
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+        update_task(1, &complete, &test_db).unwrap();
+        assert_eq!(vec![2], ready(&test_db));
+        assert_eq!(vec![2], get_task(3, &test_db).unwrap().blocked_by);
+
+        remove_dependencies(3, &[2], &test_db).unwrap();
+        assert_eq!(vec![2, 3], ready(&test_db));
+    }
+
+    #[test]
+    fn reminders_go_off_once() {
+        let test_db = test_database("reminders_go_off_once");
+
+        for (task, complete) in [("Call bank", false), ("Done already", true)] {
+            TodoData {
+                project: String::from("Home"),
+                task: String::from(task),
+                due_date: date("2024-06-14"),
+                complete,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let at = |value: &str| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap();
+
+        assert_eq!(
+            at("2024-06-13 09:00"),
+            set_reminder(1, ReminderTime::BeforeDue(TimeDelta::days(1)), &test_db).unwrap()
+        );
+        set_reminder(1, ReminderTime::At(at("2024-06-20 12:00")), &test_db).unwrap();
+        set_reminder(2, ReminderTime::At(at("2024-06-01 12:00")), &test_db).unwrap();
+        assert!(matches!(
+            set_reminder(42, ReminderTime::At(at("2024-06-01 12:00")), &test_db),
+            Err(TodoError::TaskNotFound(42))
+        ));
+
+        assert!(due_reminders(at("2024-06-13 08:59"), &test_db)
+            .unwrap()
+            .is_empty());
+
+        let due = due_reminders(at("2024-06-13 10:00"), &test_db).unwrap();
+        assert_eq!(1, due.len());
+        assert_eq!("Call bank", due[0].task.task);
+
+        mark_reminder_delivered(due[0].id, at("2024-06-13 10:00"), &test_db).unwrap();
+        assert!(due_reminders(at("2024-06-13 10:00"), &test_db)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            1,
+            due_reminders(at("2024-06-21 00:00"), &test_db)
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn timestamps_are_kept_and_queryable() {
+        let test_db = test_database("timestamps_are_kept_and_queryable");
+
+        for task in ["Old task", "New task"] {
+            TodoData {
+                project: String::from("Home"),
+                task: String::from(task),
+                due_date: date("2999-01-01"),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let task = get_task(1, &test_db).unwrap();
+        assert!(task.created_at.is_some());
+        assert_eq!(task.created_at, task.updated_at);
+        assert_eq!(None, task.completed_at);
+
+        let complete = |complete| TaskChanges {
+            complete: Some(complete),
+            ..TaskChanges::default()
+        };
+        update_task(1, &complete(true), &test_db).unwrap();
+        assert!(get_task(1, &test_db).unwrap().completed_at.is_some());
+        update_task(1, &complete(false), &test_db).unwrap();
+        assert_eq!(None, get_task(1, &test_db).unwrap().completed_at);
+        assert_eq!(None, average_days_to_complete(&test_db).unwrap());
+
+        // Backdate the first task so it took exactly two days
+        open(&test_db)
+            .unwrap()
+            .execute(
+                "UPDATE data SET complete = 1, created_at = '2024-06-01 09:00:00',
+                    completed_at = '2024-06-03 09:00:00' WHERE id = 1",
+                params![],
+            )
+            .unwrap();
+        assert_eq!(Some(2.0), average_days_to_complete(&test_db).unwrap());
+
+        let ids = |filter: TaskFilter| -> Vec<u64> {
+            query_tasks(&filter, &TaskSort::default(), &test_db)
+                .unwrap()
+                .iter()
+                .map(|task| task.id)
+                .collect()
+        };
+        assert_eq!(
+            vec![1],
+            ids(TaskFilter {
+                created_before: Some(date("2024-06-02")),
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec![2],
+            ids(TaskFilter {
+                created_after: Some(date("2024-06-02")),
+                ..TaskFilter::default()
+            })
+        );
+        assert_eq!(
+            vec![1],
+            ids(TaskFilter {
+                completed_after: Some(date("2024-06-02")),
+                ..TaskFilter::default()
+            })
+        );
+    }
+
+    #[test]
+    fn archived_tasks_can_be_restored() {
+        let test_db = test_database("archived_tasks_can_be_restored");
+
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Report"),
+            due_date: date("2024-06-14"),
+            complete: true,
+            tags: vec![String::from("q3"), String::from("team")],
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+        let before = get_task(1, &test_db).unwrap();
+
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+
+        let archived = get_all_archived_tasks(&test_db).unwrap();
+        assert_eq!(1, archived[0].id);
+        assert!(archived[0].archived_at.is_some());
+
+        // The archived task keeps its id to itself
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Slides"),
+            due_date: date("2024-06-14"),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+        assert_eq!(2, get_all_tasks(&test_db).unwrap()[0].id);
+
+        unarchive_task(1, &test_db).unwrap();
+
+        let restored = get_task(1, &test_db).unwrap();
+        assert_eq!(vec!["q3", "team"], restored.tags);
+        assert_eq!(before.created_at, restored.created_at);
+        assert_eq!(before.completed_at, restored.completed_at);
+        assert_eq!(untimed(vec![before]), untimed(vec![restored]));
+        assert!(get_all_archived_tasks(&test_db).unwrap().is_empty());
+
+        assert!(matches!(
+            unarchive_task(1, &test_db),
+            Err(TodoError::TaskNotFound(1))
+        ));
+    }
+
+    #[test]
+    fn bulk_archive_takes_matching_tasks_in_one_go() {
+        let test_db = test_database("bulk_archive_takes_matching_tasks_in_one_go");
+
+        for (project, task, complete, parent_id) in [
+            ("Work", "Report", true, None),
+            ("Work", "Draft", true, Some(1)),
+            ("Work", "Slides", false, None),
+            ("Home", "Milk", true, None),
+            ("Home", "Bread", false, Some(4)),
+        ] {
+            TodoData {
+                project: String::from(project),
+                task: String::from(task),
+                due_date: date("2999-01-01"),
+                complete,
+                parent_id,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let matching = |filter: &TaskFilter| -> Vec<u64> {
+            query_tasks(filter, &TaskSort::default(), &test_db)
+                .unwrap()
+                .iter()
+                .map(|task| task.id)
+                .collect()
+        };
+
+        let completed = TaskFilter {
+            status: TaskStatus::Done,
+            ..TaskFilter::default()
+        };
+
+        // Milk has a pending subtask, so nothing is archived
+        assert!(matches!(
+            archive_tasks(&matching(&completed), SubtaskAction::Refuse, &test_db),
+            Err(TodoError::HasSubtasks(4))
+        ));
+        assert_eq!(5, get_all_tasks(&test_db).unwrap().len());
+
+        let work = TaskFilter {
+            project: Some(String::from("Work")),
+            ..completed
+        };
+        // Draft matches too, so Report has no subtasks left by its turn
+        assert_eq!(
+            2,
+            archive_tasks(&matching(&work), SubtaskAction::Refuse, &test_db).unwrap()
+        );
+
+        let ids: Vec<u64> = get_all_tasks(&test_db)
+            .unwrap()
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(vec![3, 4, 5], ids);
+
+        let old = TaskFilter {
+            created_before: Some(date("2000-01-01")),
+            ..TaskFilter::default()
+        };
+        assert_eq!(
+            0,
+            archive_tasks(&matching(&old), SubtaskAction::Refuse, &test_db).unwrap()
+        );
+
+        // Tasks added before timestamps were kept are the oldest of all
+        open(&test_db)
+            .unwrap()
+            .execute("UPDATE data SET created_at = NULL WHERE id = 5", params![])
+            .unwrap();
+        assert_eq!(
+            1,
+            archive_tasks(&matching(&old), SubtaskAction::Refuse, &test_db).unwrap()
+        );
+        assert_eq!(5, get_all_archived_tasks(&test_db).unwrap()[2].id);
+    }
+
+    #[test]
+    fn purging_deletes_archived_tasks_for_good() {
+        let test_db = test_database("purging_deletes_archived_tasks_for_good");
+
+        for task in ["Old", "Recent"] {
+            TodoData {
+                project: String::from("Work"),
+                task: String::from(task),
+                due_date: date("2024-06-14"),
+                complete: true,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+        archive_task(2, SubtaskAction::Refuse, &test_db).unwrap();
+        open(&test_db)
+            .unwrap()
+            .execute(
+                "UPDATE archived_data SET archived_date = '2020-03-01 10:00:00' WHERE id = 1",
+                (),
+            )
+            .unwrap();
+
+        let expired = ArchiveFilter {
+            archived_before: Some(date("2021-01-01")),
+            ..ArchiveFilter::default()
+        };
+        let ids: Vec<u64> = get_archived_tasks(&expired, &test_db)
+            .unwrap()
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(vec![1], ids);
+
+        // Ids no longer in the archive are skipped
+        assert_eq!(1, purge_archived_tasks(&[1, 7], &test_db).unwrap());
+        assert_eq!(0, purge_archived_tasks(&[], &test_db).unwrap());
+
+        let left = get_all_archived_tasks(&test_db).unwrap();
+        assert_eq!(
+            vec!["Recent"],
+            left.iter().map(|task| &task.task).collect::<Vec<_>>()
+        );
+        assert!(search_tasks("old", true, &test_db).unwrap().is_empty());
+        let history = history::task_history(1, &test_db).unwrap();
+        let purge = history.last().unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(EventAction::Purge, purge.action);
+        assert_eq!("Old", purge.task);
+        assert_eq!(
+            Some(&serde_json::Value::from("Work")),
+            purge.old_values.as_ref().unwrap().get("project")
+        );
+        assert_eq!(None, purge.new_values);
+    }
+
+    #[test]
+    fn ranges_skip_ids_without_a_task() {
+        let test_db = test_database("ranges_skip_ids_without_a_task");
+
+        for task in ["Draft", "Review", "Send"] {
+            TodoData {
+                project: String::from("Work"),
+                task: String::from(task),
+                due_date: date("2999-01-01"),
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+        delete_task(2, SubtaskAction::Refuse, &test_db).unwrap();
+
+        let range = |input| crate::ids::parse_id_range(input).unwrap();
+        let ids = |tasks: Vec<TodoView>| tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![1, 3],
+            ids(get_tasks_by_id(&[range("1-10")], &test_db).unwrap())
+        );
+        assert_eq!(
+            vec![3, 1],
+            ids(get_tasks_by_id(&[range("3"), range("1-2")], &test_db).unwrap())
+        );
+        assert!(matches!(
+            get_tasks_by_id(&[range("1-3"), range("2")], &test_db),
+            Err(TodoError::TaskNotFound(2))
+        ));
+        assert!(matches!(
+            get_tasks_by_id(&[range("5-9")], &test_db),
+            Err(TodoError::TaskNotFound(5))
+        ));
+    }
+
+    #[test]
+    fn batches_change_all_tasks_or_none() {
+        let test_db = test_database("batches_change_all_tasks_or_none");
+
+        for (task, parent_id) in [("Sprint", None), ("Story", Some(1)), ("Bug", None)] {
+            TodoData {
+                project: String::from("Sprint12"),
+                task: String::from(task),
+                due_date: date("2999-01-01"),
+                parent_id,
+                ..TodoData::default()
+            }
+            .write_data(&test_db)
+            .expect("database does not exist");
+        }
+
+        let complete = TaskChanges {
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+        assert!(matches!(
+            update_tasks(&[1, 9, 3], &complete, &test_db),
+            Err(TodoError::TaskNotFound(9))
+        ));
+        assert!(get_all_tasks(&test_db)
+            .unwrap()
+            .iter()
+            .all(|task| !task.complete));
+
+        update_tasks(&[1, 3], &complete, &test_db).unwrap();
+        let done: Vec<bool> = get_all_tasks(&test_db)
+            .unwrap()
+            .iter()
+            .map(|task| task.complete)
+            .collect();
+        assert_eq!(vec![true, false, true], done);
+
+        // The whole batch is one step to undo
+        let undone = undo::undo(1, &test_db).unwrap();
+        assert_eq!(2, undone[0].events.len());
+
+        // Story goes first, so Sprint has no subtasks left by its turn
+        assert_eq!(
+            2,
+            delete_tasks(&[1, 2], SubtaskAction::Refuse, &test_db).unwrap()
+        );
+        assert!(matches!(
+            delete_tasks(&[], SubtaskAction::Refuse, &test_db),
+            Ok(0)
+        ));
+        assert_eq!(1, get_all_tasks(&test_db).unwrap().len());
+    }
 }
@@ -0,0 +1,402 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+    Connection, OptionalExtension, Row, ToSql,
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{open_existing, TodoView, ARCHIVED_TASK_COLUMNS, NOW, TASK_COLUMNS};
+use crate::error::{Result, TodoError};
+
+/// Task fields kept in the history, anything else about a task can't be edited directly
+const TRACKED_FIELDS: [&str; 8] = [
+    "project",
+    "task",
+    "due_date",
+    "complete",
+    "priority",
+    "tags",
+    "parent_id",
+    "notes",
+];
+
+/// Values of the tracked fields of a task, by field name
+pub type TaskState = Map<String, Value>;
+
+/// What happened to a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventAction {
+    Create,
+    Edit,
+    Complete,
+    Archive,
+    /// Brought back from the archive
+    Restore,
+    Delete,
+    /// Deleted from the archive for good
+    Purge,
+    /// An undo reverted the task to these values
+    Undo,
+}
+
+impl fmt::Display for EventAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Create => "create",
+            Self::Edit => "edit",
+            Self::Complete => "complete",
+            Self::Archive => "archive",
+            Self::Restore => "restore",
+            Self::Delete => "delete",
+            Self::Purge => "purge",
+            Self::Undo => "undo",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl ToSql for EventAction {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for EventAction {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "create" => Ok(Self::Create),
+            "edit" => Ok(Self::Edit),
+            "complete" => Ok(Self::Complete),
+            "archive" => Ok(Self::Archive),
+            "restore" => Ok(Self::Restore),
+            "delete" => Ok(Self::Delete),
+            "purge" => Ok(Self::Purge),
+            "undo" => Ok(Self::Undo),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// One entry in the history of a task
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TaskEvent {
+    pub id: u64,
+    pub task_id: u64,
+    /// Description of the task when the event happened
+    pub task: String,
+    pub action: EventAction,
+    /// Values before the event, only the changed ones for edits, `None` for new tasks
+    pub old_values: Option<TaskState>,
+    /// Values after the event, only the changed ones for edits, `None` once the task is gone
+    pub new_values: Option<TaskState>,
+    /// Local time of the event
+    pub at: NaiveDateTime,
+}
+
+impl TaskEvent {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let state = |index| {
+            row.get::<_, Option<String>>(index)
+                .map(|json| json.and_then(|json| serde_json::from_str::<TaskState>(&json).ok()))
+        };
+
+        Ok(Self {
+            id: row.get(0)?,
+            task_id: row.get(1)?,
+            task: row.get(2)?,
+            action: row.get(3)?,
+            old_values: state(4)?,
+            new_values: state(5)?,
+            at: row.get(6)?,
+        })
+    }
+}
+
+/// Reads the tracked fields of a live task, `None` if it doesn't exist
+pub(super) fn task_state(conn: &Connection, task_id: u64) -> Result<Option<TaskState>> {
+    state_of(
+        conn,
+        &format!("SELECT {TASK_COLUMNS} FROM data WHERE id = ?1"),
+        task_id,
+    )
+}
+
+/// Reads the tracked fields of an archived task, `None` if it isn't in the archive
+pub(super) fn archived_task_state(conn: &Connection, task_id: u64) -> Result<Option<TaskState>> {
+    state_of(
+        conn,
+        &format!("SELECT {ARCHIVED_TASK_COLUMNS} FROM archived_data WHERE id = ?1"),
+        task_id,
+    )
+}
+
+/// Reads the tracked fields of the task a query selects by id
+fn state_of(conn: &Connection, sql: &str, task_id: u64) -> Result<Option<TaskState>> {
+    let task = conn
+        .query_row(sql, params![task_id], TodoView::from_row)
+        .optional()?;
+
+    Ok(task.map(|task| {
+        let Ok(Value::Object(mut fields)) = serde_json::to_value(task) else {
+            unreachable!("tasks always serialise to a JSON object");
+        };
+        fields.retain(|field, _| TRACKED_FIELDS.contains(&field.as_str()));
+
+        fields
+    }))
+}
+
+/// Appends an event to the history of a task, as part of the operation in progress if any
+///
+/// Edits only keep the fields that changed, an edit that changed nothing isn't recorded.
+pub(super) fn record(
+    conn: &Connection,
+    task_id: u64,
+    action: EventAction,
+    old: Option<&TaskState>,
+    new: Option<&TaskState>,
+) -> Result<()> {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => {
+            let changed = |from: &TaskState, to: &TaskState| -> TaskState {
+                from.iter()
+                    .filter(|(field, value)| to.get(*field) != Some(value))
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect()
+            };
+
+            (Some(changed(old, new)), Some(changed(new, old)))
+        }
+        (old, new) => (old.cloned(), new.cloned()),
+    };
+
+    if old.as_ref().is_some_and(Map::is_empty) || (old.is_none() && new.is_none()) {
+        return Ok(());
+    }
+
+    let description = new
+        .as_ref()
+        .and_then(|state| state.get("task"))
+        .or_else(|| old.as_ref().and_then(|state| state.get("task")))
+        .and_then(Value::as_str)
+        .map(String::from);
+    let description = match description {
+        Some(description) => description,
+        // The description didn't change, so it isn't in the recorded values
+        None => conn.query_row(
+            "SELECT task FROM data WHERE id = ?1",
+            params![task_id],
+            |row| row.get(0),
+        )?,
+    };
+
+    let json = |state: Option<TaskState>| state.map(|state| Value::Object(state).to_string());
+    conn.execute(
+        &format!(
+            "INSERT INTO task_events (task_id, task, action, old_values, new_values, at,
+                operation_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, {NOW},
+                (SELECT id FROM operations WHERE in_progress))"
+        ),
+        params![task_id, description, action, json(old), json(new)],
+    )?;
+
+    Ok(())
+}
+
+/// Records the changes made to a live task since `before` was read
+///
+/// Completing a pending task is recorded as `Complete`, any other change as `Edit`.
+pub(super) fn record_update(conn: &Connection, task_id: u64, before: &TaskState) -> Result<()> {
+    let after = task_state(conn, task_id)?;
+
+    let completed = |state: &TaskState| state.get("complete") == Some(&Value::Bool(true));
+    let action = match &after {
+        Some(after) if completed(after) && !completed(before) => EventAction::Complete,
+        _ => EventAction::Edit,
+    };
+
+    record(conn, task_id, action, Some(before), after.as_ref())
+}
+
+const EVENT_COLUMNS: &str = "id, task_id, task, action, old_values, new_values, at";
+
+/// Gets the history of a task, oldest event first
+///
+/// # Errors
+///
+/// Returns `TodoError::TaskNotFound` if the task has no history and doesn't exist, or an
+/// error if database operations fail
+pub fn task_history(task_id: u64, db_file: &str) -> Result<Vec<TaskEvent>> {
+    let conn = open_existing(db_file)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {EVENT_COLUMNS} FROM task_events WHERE task_id = ?1 ORDER BY id;"
+    ))?;
+
+    let events_iter = stmt.query_map(params![task_id], TaskEvent::from_row)?;
+
+    let mut result = Vec::new();
+
+    for event in events_iter {
+        result.push(event?);
+    }
+
+    // Tasks added before the history was kept exist without any events
+    if result.is_empty() {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM data WHERE id = ?1)
+                OR EXISTS (SELECT 1 FROM archived_data WHERE id = ?1)",
+            params![task_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(TodoError::TaskNotFound(task_id));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Gets the events recorded by an operation, oldest first
+pub(super) fn operation_events(conn: &Connection, operation_id: u64) -> Result<Vec<TaskEvent>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {EVENT_COLUMNS} FROM task_events WHERE operation_id = ?1 ORDER BY id;"
+    ))?;
+
+    let events_iter = stmt.query_map(params![operation_id], TaskEvent::from_row)?;
+
+    let mut result = Vec::new();
+
+    for event in events_iter {
+        result.push(event?);
+    }
+
+    Ok(result)
+}
+
+/// Gets the most recent events across all tasks, newest first
+///
+/// # Errors
+///
+/// Returns an error if database operations fail
+pub fn recent_events(limit: u32, db_file: &str) -> Result<Vec<TaskEvent>> {
+    let conn = open_existing(db_file)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {EVENT_COLUMNS} FROM task_events ORDER BY id DESC LIMIT ?1;"
+    ))?;
+
+    let events_iter = stmt.query_map(params![limit], TaskEvent::from_row)?;
+
+    let mut result = Vec::new();
+
+    for event in events_iter {
+        result.push(event?);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        archive_task, delete_task, open, test_database, update_task, SubtaskAction, TaskChanges,
+        TodoData,
+    };
+
+    #[test]
+    fn history_follows_a_task_until_it_is_gone() {
+        let test_db = test_database("history_follows_a_task_until_it_is_gone");
+
+        TodoData {
+            project: String::from("Home"),
+            task: String::from("Buy milk"),
+            due_date: "2024-06-14".parse().unwrap(),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+
+        let changes = TaskChanges {
+            task: Some(String::from("Buy oat milk")),
+            complete: Some(true),
+            ..TaskChanges::default()
+        };
+        update_task(1, &changes, &test_db).unwrap();
+        // Nothing changes, so nothing is recorded
+        update_task(1, &changes, &test_db).unwrap();
+        delete_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+
+        let history = task_history(1, &test_db).unwrap();
+        let actions: Vec<EventAction> = history.iter().map(|event| event.action).collect();
+        assert_eq!(
+            vec![
+                EventAction::Create,
+                EventAction::Complete,
+                EventAction::Delete
+            ],
+            actions
+        );
+
+        assert_eq!(None, history[0].old_values);
+        assert_eq!(
+            Some(&Value::from("2024-06-14")),
+            history[0].new_values.as_ref().unwrap().get("due_date")
+        );
+
+        let old = history[1].old_values.as_ref().unwrap();
+        let new = history[1].new_values.as_ref().unwrap();
+        assert_eq!(vec!["complete", "task"], old.keys().collect::<Vec<_>>());
+        assert_eq!(Some(&Value::from("Buy milk")), old.get("task"));
+        assert_eq!(Some(&Value::from(true)), new.get("complete"));
+        assert_eq!("Buy oat milk", history[1].task);
+
+        assert_eq!("Buy oat milk", history[2].task);
+        assert_eq!(None, history[2].new_values);
+
+        let recent = recent_events(2, &test_db).unwrap();
+        assert_eq!(
+            vec![3, 2],
+            recent.iter().map(|event| event.id).collect::<Vec<_>>()
+        );
+
+        assert!(matches!(
+            task_history(2, &test_db),
+            Err(TodoError::TaskNotFound(2))
+        ));
+    }
+
+    #[test]
+    fn archived_tasks_without_events_have_an_empty_history() {
+        let test_db = test_database("archived_tasks_without_events_have_an_empty_history");
+
+        TodoData {
+            project: String::from("Home"),
+            task: String::from("Buy milk"),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+
+        // Like a task archived before the history was kept
+        open(&test_db)
+            .unwrap()
+            .execute("DELETE FROM task_events", ())
+            .unwrap();
+
+        assert!(task_history(1, &test_db).unwrap().is_empty());
+        assert!(matches!(
+            task_history(2, &test_db),
+            Err(TodoError::TaskNotFound(2))
+        ));
+    }
+}
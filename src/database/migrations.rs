@@ -0,0 +1,594 @@
+use rusqlite::{Connection, Result};
+
+/// Ordered schema upgrades, the schema version stored in `PRAGMA user_version`
+/// is the number of steps that have been applied.
+///
+/// Steps are only ever appended, an existing step must never be edited once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema, databases created before versioning already have these tables
+    "CREATE TABLE IF NOT EXISTS data (
+        id INTEGER PRIMARY KEY NOT NULL,
+        project VARCHAR(50) NOT NULL,
+        task VARCHAR(100) NOT NULL,
+        due_date DATE,
+        complete BOOLEAN NOT NULL CHECK (complete IN (0, 1))
+    );
+    CREATE TABLE IF NOT EXISTS archived_data (
+        id INTEGER PRIMARY KEY NOT NULL,
+        project VARCHAR(50) NOT NULL,
+        task VARCHAR(100) NOT NULL,
+        due_date DATE,
+        complete BOOLEAN NOT NULL CHECK (complete IN (0, 1)),
+        archived_date DATETIME DEFAULT CURRENT_TIMESTAMP
+    );",
+    // 2: store due dates in the canonical 'YYYY-MM-DD' form, unparseable dates are left alone
+    "UPDATE data SET due_date = date(due_date)
+        WHERE date(due_date) IS NOT NULL AND due_date != date(due_date);
+    UPDATE archived_data SET due_date = date(due_date)
+        WHERE date(due_date) IS NOT NULL AND due_date != date(due_date);",
+    // 3: task priorities, 1 (low) to 4 (urgent)
+    "ALTER TABLE data ADD COLUMN priority INTEGER NOT NULL DEFAULT 2
+        CHECK (priority BETWEEN 1 AND 4);
+    ALTER TABLE archived_data ADD COLUMN priority INTEGER NOT NULL DEFAULT 2
+        CHECK (priority BETWEEN 1 AND 4);",
+    // 4: tags, archived tasks keep a comma separated snapshot of their tags
+    "CREATE TABLE tags (
+        id INTEGER PRIMARY KEY NOT NULL,
+        name VARCHAR(50) NOT NULL UNIQUE
+    );
+    CREATE TABLE task_tags (
+        task_id INTEGER NOT NULL REFERENCES data (id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+        PRIMARY KEY (task_id, tag_id)
+    );
+    ALTER TABLE archived_data ADD COLUMN tags TEXT;",
+    // 5: full-text search over live and archived tasks, kept in sync by triggers
+    "CREATE VIRTUAL TABLE task_search USING fts5 (
+        task,
+        project,
+        notes,
+        archived UNINDEXED,
+        task_id UNINDEXED,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    INSERT INTO task_search (task_search, rank) VALUES ('rank', 'bm25(10.0, 5.0, 1.0)');
+    INSERT INTO task_search (task, project, archived, task_id)
+        SELECT task, project, 0, id FROM data;
+    INSERT INTO task_search (task, project, archived, task_id)
+        SELECT task, project, 1, id FROM archived_data;
+    CREATE TRIGGER data_search_insert AFTER INSERT ON data BEGIN
+        INSERT INTO task_search (task, project, archived, task_id)
+        VALUES (new.task, new.project, 0, new.id);
+    END;
+    CREATE TRIGGER data_search_update AFTER UPDATE ON data BEGIN
+        UPDATE task_search SET task = new.task, project = new.project, task_id = new.id
+        WHERE archived = 0 AND task_id = old.id;
+    END;
+    CREATE TRIGGER data_search_delete AFTER DELETE ON data BEGIN
+        DELETE FROM task_search WHERE archived = 0 AND task_id = old.id;
+    END;
+    CREATE TRIGGER archived_data_search_insert AFTER INSERT ON archived_data BEGIN
+        INSERT INTO task_search (task, project, archived, task_id)
+        VALUES (new.task, new.project, 1, new.id);
+    END;
+    CREATE TRIGGER archived_data_search_update AFTER UPDATE ON archived_data BEGIN
+        UPDATE task_search SET task = new.task, project = new.project, task_id = new.id
+        WHERE archived = 1 AND task_id = old.id;
+    END;
+    CREATE TRIGGER archived_data_search_delete AFTER DELETE ON archived_data BEGIN
+        DELETE FROM task_search WHERE archived = 1 AND task_id = old.id;
+    END;",
+    // 6: recurring tasks, each occurrence is a task linked to its series
+    "CREATE TABLE recurrences (
+        id INTEGER PRIMARY KEY NOT NULL,
+        rule VARCHAR(20) NOT NULL,
+        paused BOOLEAN NOT NULL DEFAULT 0 CHECK (paused IN (0, 1))
+    );
+    ALTER TABLE data ADD COLUMN recurrence_id INTEGER
+        REFERENCES recurrences (id) ON DELETE SET NULL;",
+    // 7: subtasks, deleting or archiving a parent must deal with its children first
+    "ALTER TABLE data ADD COLUMN parent_id INTEGER REFERENCES data (id);
+    ALTER TABLE archived_data ADD COLUMN parent_id INTEGER;",
+    // 8: dependencies between tasks, removing either task removes the dependency
+    "CREATE TABLE task_dependencies (
+        task_id INTEGER NOT NULL REFERENCES data (id) ON DELETE CASCADE,
+        depends_on_id INTEGER NOT NULL REFERENCES data (id) ON DELETE CASCADE,
+        PRIMARY KEY (task_id, depends_on_id),
+        CHECK (task_id != depends_on_id)
+    );",
+    // 9: reminders, in local time, delivered ones are kept so they only fire once
+    "CREATE TABLE reminders (
+        id INTEGER PRIMARY KEY NOT NULL,
+        task_id INTEGER NOT NULL REFERENCES data (id) ON DELETE CASCADE,
+        remind_at DATETIME NOT NULL,
+        delivered_at DATETIME
+    );",
+    // 10: task timestamps in local time, unknown for tasks created before this version
+    "ALTER TABLE data ADD COLUMN created_at DATETIME;
+    ALTER TABLE data ADD COLUMN updated_at DATETIME;
+    ALTER TABLE data ADD COLUMN completed_at DATETIME;
+    ALTER TABLE archived_data ADD COLUMN created_at DATETIME;
+    ALTER TABLE archived_data ADD COLUMN updated_at DATETIME;
+    ALTER TABLE archived_data ADD COLUMN completed_at DATETIME;",
+    // 11: append-only history of task changes, kept after the task itself is gone
+    "CREATE TABLE task_events (
+        id INTEGER PRIMARY KEY NOT NULL,
+        task_id INTEGER NOT NULL,
+        task VARCHAR(100) NOT NULL,
+        action VARCHAR(10) NOT NULL,
+        old_values TEXT,
+        new_values TEXT,
+        at DATETIME NOT NULL
+    );
+    CREATE INDEX task_events_task_id ON task_events (task_id);",
+    // 12: undo, while an operation is in progress triggers journal the statements that
+    // reverse each change. Later steps adding columns to these tables must recreate them
+    "CREATE TABLE operations (
+        id INTEGER PRIMARY KEY NOT NULL,
+        at DATETIME NOT NULL,
+        in_progress BOOLEAN NOT NULL DEFAULT 1 CHECK (in_progress IN (0, 1))
+    );
+    CREATE INDEX operations_in_progress ON operations (in_progress) WHERE in_progress;
+    CREATE TABLE undo_log (
+        id INTEGER PRIMARY KEY NOT NULL,
+        operation_id INTEGER NOT NULL REFERENCES operations (id) ON DELETE CASCADE,
+        statement TEXT NOT NULL
+    );
+    CREATE INDEX undo_log_operation_id ON undo_log (operation_id);
+    ALTER TABLE task_events ADD COLUMN operation_id INTEGER;
+    CREATE INDEX task_events_operation_id ON task_events (operation_id);
+    CREATE TRIGGER data_undo_insert AFTER INSERT ON data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM data WHERE id = ' || quote(new.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER data_undo_update AFTER UPDATE ON data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE data SET project = ' || quote(old.project)
+            || ', task = ' || quote(old.task)
+            || ', due_date = ' || quote(old.due_date)
+            || ', complete = ' || quote(old.complete)
+            || ', priority = ' || quote(old.priority)
+            || ', recurrence_id = ' || quote(old.recurrence_id)
+            || ', parent_id = ' || quote(old.parent_id)
+            || ', created_at = ' || quote(old.created_at)
+            || ', updated_at = ' || quote(old.updated_at)
+            || ', completed_at = ' || quote(old.completed_at)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER data_undo_delete AFTER DELETE ON data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO data ('
+            || 'id, project, task, due_date, complete, priority, recurrence_id, '
+            || 'parent_id, created_at, updated_at, completed_at) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.project)
+            || ', ' || quote(old.task)
+            || ', ' || quote(old.due_date)
+            || ', ' || quote(old.complete)
+            || ', ' || quote(old.priority)
+            || ', ' || quote(old.recurrence_id)
+            || ', ' || quote(old.parent_id)
+            || ', ' || quote(old.created_at)
+            || ', ' || quote(old.updated_at)
+            || ', ' || quote(old.completed_at)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER archived_data_undo_insert AFTER INSERT ON archived_data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM archived_data WHERE id = ' || quote(new.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER archived_data_undo_update AFTER UPDATE ON archived_data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE archived_data SET project = ' || quote(old.project)
+            || ', task = ' || quote(old.task)
+            || ', due_date = ' || quote(old.due_date)
+            || ', complete = ' || quote(old.complete)
+            || ', archived_date = ' || quote(old.archived_date)
+            || ', priority = ' || quote(old.priority)
+            || ', tags = ' || quote(old.tags)
+            || ', parent_id = ' || quote(old.parent_id)
+            || ', created_at = ' || quote(old.created_at)
+            || ', updated_at = ' || quote(old.updated_at)
+            || ', completed_at = ' || quote(old.completed_at)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER archived_data_undo_delete AFTER DELETE ON archived_data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO archived_data ('
+            || 'id, project, task, due_date, complete, archived_date, priority, tags, '
+            || 'parent_id, created_at, updated_at, completed_at) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.project)
+            || ', ' || quote(old.task)
+            || ', ' || quote(old.due_date)
+            || ', ' || quote(old.complete)
+            || ', ' || quote(old.archived_date)
+            || ', ' || quote(old.priority)
+            || ', ' || quote(old.tags)
+            || ', ' || quote(old.parent_id)
+            || ', ' || quote(old.created_at)
+            || ', ' || quote(old.updated_at)
+            || ', ' || quote(old.completed_at)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER tags_undo_insert AFTER INSERT ON tags BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM tags WHERE id = ' || quote(new.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER tags_undo_delete AFTER DELETE ON tags BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO tags (id, name) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.name)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER task_tags_undo_insert AFTER INSERT ON task_tags BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM task_tags WHERE task_id = ' || quote(new.task_id)
+            || ' AND tag_id = ' || quote(new.tag_id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER task_tags_undo_delete AFTER DELETE ON task_tags BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO task_tags (task_id, tag_id) VALUES ('
+            || quote(old.task_id)
+            || ', ' || quote(old.tag_id)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER recurrences_undo_insert AFTER INSERT ON recurrences BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM recurrences WHERE id = ' || quote(new.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER recurrences_undo_update AFTER UPDATE ON recurrences BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE recurrences SET rule = ' || quote(old.rule)
+            || ', paused = ' || quote(old.paused)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER recurrences_undo_delete AFTER DELETE ON recurrences BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO recurrences (id, rule, paused) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.rule)
+            || ', ' || quote(old.paused)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER task_dependencies_undo_insert AFTER INSERT ON task_dependencies BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM task_dependencies WHERE task_id = ' || quote(new.task_id)
+            || ' AND depends_on_id = ' || quote(new.depends_on_id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER task_dependencies_undo_delete AFTER DELETE ON task_dependencies BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO task_dependencies (task_id, depends_on_id) VALUES ('
+            || quote(old.task_id)
+            || ', ' || quote(old.depends_on_id)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER reminders_undo_insert AFTER INSERT ON reminders BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'DELETE FROM reminders WHERE id = ' || quote(new.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER reminders_undo_update AFTER UPDATE ON reminders BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE reminders SET task_id = ' || quote(old.task_id)
+            || ', remind_at = ' || quote(old.remind_at)
+            || ', delivered_at = ' || quote(old.delivered_at)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER reminders_undo_delete AFTER DELETE ON reminders BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO reminders (id, task_id, remind_at, delivered_at) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.task_id)
+            || ', ' || quote(old.remind_at)
+            || ', ' || quote(old.delivered_at)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;",
+    // 13: archived tasks keep their task id and archive dates are local time like other
+    // timestamps. Tasks archived earlier got ids of their own, they move above every id in
+    // use so restoring them can't clash. The undo journal refers to the old ids, so it goes
+    "UPDATE archived_data SET id = id + max(
+        coalesce((SELECT max(id) FROM data), 0),
+        coalesce((SELECT max(id) FROM archived_data), 0),
+        coalesce((SELECT max(task_id) FROM task_events), 0)
+    );
+    UPDATE archived_data SET archived_date = datetime(archived_date, 'localtime')
+        WHERE archived_date IS NOT NULL;
+    DELETE FROM undo_log;
+    DELETE FROM operations;",
+    // 14: notes on tasks, searchable along with the description and project. Recreates the
+    // search and undo triggers of both task tables to cover them
+    "ALTER TABLE data ADD COLUMN notes TEXT;
+    ALTER TABLE archived_data ADD COLUMN notes TEXT;
+    DROP TRIGGER data_search_insert;
+    DROP TRIGGER data_search_update;
+    DROP TRIGGER archived_data_search_insert;
+    DROP TRIGGER archived_data_search_update;
+    CREATE TRIGGER data_search_insert AFTER INSERT ON data BEGIN
+        INSERT INTO task_search (task, project, notes, archived, task_id)
+        VALUES (new.task, new.project, new.notes, 0, new.id);
+    END;
+    CREATE TRIGGER data_search_update AFTER UPDATE ON data BEGIN
+        UPDATE task_search
+        SET task = new.task, project = new.project, notes = new.notes, task_id = new.id
+        WHERE archived = 0 AND task_id = old.id;
+    END;
+    CREATE TRIGGER archived_data_search_insert AFTER INSERT ON archived_data BEGIN
+        INSERT INTO task_search (task, project, notes, archived, task_id)
+        VALUES (new.task, new.project, new.notes, 1, new.id);
+    END;
+    CREATE TRIGGER archived_data_search_update AFTER UPDATE ON archived_data BEGIN
+        UPDATE task_search
+        SET task = new.task, project = new.project, notes = new.notes, task_id = new.id
+        WHERE archived = 1 AND task_id = old.id;
+    END;
+    DROP TRIGGER data_undo_update;
+    DROP TRIGGER data_undo_delete;
+    DROP TRIGGER archived_data_undo_update;
+    DROP TRIGGER archived_data_undo_delete;
+    CREATE TRIGGER data_undo_update AFTER UPDATE ON data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE data SET project = ' || quote(old.project)
+            || ', task = ' || quote(old.task)
+            || ', due_date = ' || quote(old.due_date)
+            || ', complete = ' || quote(old.complete)
+            || ', priority = ' || quote(old.priority)
+            || ', recurrence_id = ' || quote(old.recurrence_id)
+            || ', parent_id = ' || quote(old.parent_id)
+            || ', created_at = ' || quote(old.created_at)
+            || ', updated_at = ' || quote(old.updated_at)
+            || ', completed_at = ' || quote(old.completed_at)
+            || ', notes = ' || quote(old.notes)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER data_undo_delete AFTER DELETE ON data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO data ('
+            || 'id, project, task, due_date, complete, priority, recurrence_id, '
+            || 'parent_id, created_at, updated_at, completed_at, notes) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.project)
+            || ', ' || quote(old.task)
+            || ', ' || quote(old.due_date)
+            || ', ' || quote(old.complete)
+            || ', ' || quote(old.priority)
+            || ', ' || quote(old.recurrence_id)
+            || ', ' || quote(old.parent_id)
+            || ', ' || quote(old.created_at)
+            || ', ' || quote(old.updated_at)
+            || ', ' || quote(old.completed_at)
+            || ', ' || quote(old.notes)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER archived_data_undo_update AFTER UPDATE ON archived_data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'UPDATE archived_data SET project = ' || quote(old.project)
+            || ', task = ' || quote(old.task)
+            || ', due_date = ' || quote(old.due_date)
+            || ', complete = ' || quote(old.complete)
+            || ', archived_date = ' || quote(old.archived_date)
+            || ', priority = ' || quote(old.priority)
+            || ', tags = ' || quote(old.tags)
+            || ', parent_id = ' || quote(old.parent_id)
+            || ', created_at = ' || quote(old.created_at)
+            || ', updated_at = ' || quote(old.updated_at)
+            || ', completed_at = ' || quote(old.completed_at)
+            || ', notes = ' || quote(old.notes)
+            || ' WHERE id = ' || quote(old.id)
+        FROM operations WHERE in_progress;
+    END;
+    CREATE TRIGGER archived_data_undo_delete AFTER DELETE ON archived_data BEGIN
+        INSERT INTO undo_log (operation_id, statement)
+        SELECT id, 'INSERT INTO archived_data ('
+            || 'id, project, task, due_date, complete, archived_date, priority, tags, '
+            || 'parent_id, created_at, updated_at, completed_at, notes) VALUES ('
+            || quote(old.id)
+            || ', ' || quote(old.project)
+            || ', ' || quote(old.task)
+            || ', ' || quote(old.due_date)
+            || ', ' || quote(old.complete)
+            || ', ' || quote(old.archived_date)
+            || ', ' || quote(old.priority)
+            || ', ' || quote(old.tags)
+            || ', ' || quote(old.parent_id)
+            || ', ' || quote(old.created_at)
+            || ', ' || quote(old.updated_at)
+            || ', ' || quote(old.completed_at)
+            || ', ' || quote(old.notes)
+            || ')'
+        FROM operations WHERE in_progress;
+    END;",
+];
+
+/// Gets the schema version of the database
+///
+/// # Errors
+///
+/// Returns an error if the version cannot be read
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Brings the database schema up to date
+///
+/// Each pending step runs in its own transaction together with the version bump,
+/// so a failed upgrade leaves the database at the last good version.
+///
+/// # Errors
+///
+/// Returns an error if any of the upgrade steps fail
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+
+    for (index, step) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(step)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Latest schema version known to this build
+#[must_use]
+pub const fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_new_database_to_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(Ok(latest_version()), schema_version(&conn));
+    }
+
+    #[test]
+    fn migrating_twice_is_a_no_op() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(Ok(latest_version()), schema_version(&conn));
+    }
+
+    #[test]
+    fn upgrades_unversioned_database_without_losing_data() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        // Schema as created by versions before migrations existed
+        conn.execute_batch(
+            "CREATE TABLE data (
+                id INTEGER PRIMARY KEY NOT NULL,
+                project VARCHAR(50) NOT NULL,
+                task VARCHAR(100) NOT NULL,
+                due_date DATE,
+                complete BOOLEAN NOT NULL CHECK (complete IN (0, 1))
+            );
+            INSERT INTO data (project, task, due_date, complete)
+            VALUES ('Legacy', 'Old task', '2023-01-01', 0);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let task: String = conn
+            .query_row(
+                "SELECT task FROM data WHERE project = 'Legacy'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!("Old task", task);
+        assert_eq!(Ok(latest_version()), schema_version(&conn));
+    }
+
+    #[test]
+    fn canonicalizes_due_dates() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE data (
+                id INTEGER PRIMARY KEY NOT NULL,
+                project VARCHAR(50) NOT NULL,
+                task VARCHAR(100) NOT NULL,
+                due_date DATE,
+                complete BOOLEAN NOT NULL CHECK (complete IN (0, 1))
+            );
+            INSERT INTO data (project, task, due_date, complete)
+            VALUES ('Legacy', 'Timestamp', '2023-01-01 12:00:00', 0),
+                   ('Legacy', 'Garbage', 'next tuesday', 0);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let dates: Vec<String> = conn
+            .prepare("SELECT due_date FROM data ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["2023-01-01", "next tuesday"], dates);
+    }
+
+    #[test]
+    fn moves_archived_tasks_clear_of_task_ids() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE data (
+                id INTEGER PRIMARY KEY NOT NULL,
+                project VARCHAR(50) NOT NULL,
+                task VARCHAR(100) NOT NULL,
+                due_date DATE,
+                complete BOOLEAN NOT NULL CHECK (complete IN (0, 1))
+            );
+            CREATE TABLE archived_data (
+                id INTEGER PRIMARY KEY NOT NULL,
+                project VARCHAR(50) NOT NULL,
+                task VARCHAR(100) NOT NULL,
+                due_date DATE,
+                complete BOOLEAN NOT NULL CHECK (complete IN (0, 1)),
+                archived_date DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO data (id, project, task, complete) VALUES (1, 'Live', 'Kept', 0),
+                (2, 'Live', 'Also kept', 0);
+            INSERT INTO archived_data (id, project, task, complete, archived_date)
+            VALUES (1, 'Old', 'Clashing', 1, '2023-01-01 12:00:00');",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let (id, archived_date): (u64, String) = conn
+            .query_row("SELECT id, archived_date FROM archived_data", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(3, id);
+        let expected: String = conn
+            .query_row(
+                "SELECT datetime('2023-01-01 12:00:00', 'localtime')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(expected, archived_date);
+
+        let indexed: u64 = conn
+            .query_row(
+                "SELECT task_id FROM task_search WHERE archived = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(3, indexed);
+    }
+}
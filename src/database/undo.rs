@@ -0,0 +1,259 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, Transaction};
+
+use super::{
+    history::{self, EventAction, TaskEvent},
+    open_existing, NOW,
+};
+use crate::error::Result;
+
+/// Number of operations kept for undo, older ones are forgotten
+const UNDO_LIMIT: u64 = 100;
+
+/// Starts journaling changes so they can be undone together, returning the operation id
+///
+/// Every change made until `finish_operation` is recorded in `undo_log` by triggers.
+pub(super) fn begin_operation(tx: &Transaction) -> Result<u64> {
+    tx.execute(
+        &format!("INSERT INTO operations (at) VALUES ({NOW})"),
+        params![],
+    )?;
+
+    Ok(u64::try_from(tx.last_insert_rowid()).unwrap_or_default())
+}
+
+/// Stops journaling, an operation that changed nothing is dropped
+pub(super) fn finish_operation(tx: &Transaction, operation_id: u64) -> Result<()> {
+    tx.execute(
+        "UPDATE operations SET in_progress = 0 WHERE id = ?1",
+        params![operation_id],
+    )?;
+    tx.execute(
+        "DELETE FROM operations WHERE id = ?1
+            AND NOT EXISTS (SELECT 1 FROM task_events WHERE operation_id = ?1)
+            AND NOT EXISTS (SELECT 1 FROM undo_log WHERE operation_id = ?1)",
+        params![operation_id],
+    )?;
+    tx.execute(
+        "DELETE FROM operations WHERE id <= ?1 - ?2",
+        params![operation_id, UNDO_LIMIT],
+    )?;
+
+    Ok(())
+}
+
+/// An operation that was undone
+#[derive(Debug, PartialEq, Eq)]
+pub struct UndoneOperation {
+    /// Local time the operation was made
+    pub at: NaiveDateTime,
+    /// What the operation did, oldest first, empty when it changed no tracked task field
+    pub events: Vec<TaskEvent>,
+}
+
+/// Undoes the most recent operations, newest first, returning what was undone
+///
+/// Every command that changes tasks, series, dependencies, reminders or the archive is an
+/// operation. Returns fewer than `steps` operations when there aren't that many left to undo.
+///
+/// # Errors
+///
+/// Returns an error if database operations fail, in which case nothing is undone
+pub fn undo(steps: u32, db_file: &str) -> Result<Vec<UndoneOperation>> {
+    let mut conn = open_existing(db_file)?;
+
+    let tx = conn.transaction()?;
+    // Rows come back in reverse order, links between them only hold once all are back
+    tx.pragma_update(None, "defer_foreign_keys", true)?;
+
+    let mut stmt = tx
+        .prepare("SELECT id, at FROM operations WHERE in_progress = 0 ORDER BY id DESC LIMIT ?1")?;
+    let operations = stmt
+        .query_map(params![steps], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(u64, NaiveDateTime)>>>()?;
+    drop(stmt);
+
+    let mut result = Vec::new();
+    for (operation_id, at) in operations {
+        let events = history::operation_events(&tx, operation_id)?;
+        let mut task_ids: Vec<u64> = events.iter().map(|event| event.task_id).collect();
+        task_ids.sort_unstable();
+        task_ids.dedup();
+
+        let before = task_ids
+            .iter()
+            .map(|&id| history::task_state(&tx, id))
+            .collect::<Result<Vec<_>>>()?;
+
+        revert(&tx, operation_id)?;
+
+        for (&id, before) in task_ids.iter().zip(&before) {
+            let after = history::task_state(&tx, id)?;
+            history::record(&tx, id, EventAction::Undo, before.as_ref(), after.as_ref())?;
+        }
+
+        result.push(UndoneOperation { at, events });
+    }
+
+    tx.commit()?;
+
+    Ok(result)
+}
+
+/// Runs the journaled statements of an operation in reverse and forgets the operation
+fn revert(conn: &Connection, operation_id: u64) -> Result<()> {
+    let mut stmt =
+        conn.prepare("SELECT statement FROM undo_log WHERE operation_id = ?1 ORDER BY id DESC")?;
+    let statements = stmt
+        .query_map(params![operation_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    for statement in statements {
+        conn.execute_batch(&statement)?;
+    }
+
+    conn.execute(
+        "DELETE FROM operations WHERE id = ?1",
+        params![operation_id],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        add_dependencies, archive_task, delete_task, end_series, get_all_archived_tasks,
+        get_series, get_task, set_reminder, test_database, update_task, Priority, ReminderTime,
+        SubtaskAction, TaskChanges, TodoData,
+    };
+
+    fn add(task: &str, parent_id: Option<u64>, db_file: &str) {
+        TodoData {
+            project: String::from("Home"),
+            task: String::from(task),
+            due_date: "2999-01-01".parse().unwrap(),
+            tags: vec![String::from("errand")],
+            parent_id,
+            ..TodoData::default()
+        }
+        .write_data(db_file)
+        .expect("database does not exist");
+    }
+
+    #[test]
+    fn undo_restores_deleted_tasks_with_their_links() {
+        let test_db = test_database("undo_restores_deleted_tasks_with_their_links");
+
+        add("Plan trip", None, &test_db);
+        add("Book flights", Some(1), &test_db);
+        add("Pack", None, &test_db);
+        add_dependencies(3, &[2], &test_db).unwrap();
+        let remind_at = "2998-12-31T09:00:00".parse().unwrap();
+        set_reminder(2, ReminderTime::At(remind_at), &test_db).unwrap();
+
+        let before = get_task(2, &test_db).unwrap();
+        delete_task(1, SubtaskAction::Cascade, &test_db).unwrap();
+        assert!(get_task(3, &test_db).unwrap().blocked_by.is_empty());
+
+        let undone = undo(1, &test_db).unwrap();
+        assert_eq!(1, undone.len());
+        let actions: Vec<(EventAction, u64)> = undone[0]
+            .events
+            .iter()
+            .map(|event| (event.action, event.task_id))
+            .collect();
+        assert_eq!(
+            vec![(EventAction::Delete, 1), (EventAction::Delete, 2)],
+            actions
+        );
+
+        assert_eq!(before, get_task(2, &test_db).unwrap());
+        assert_eq!(vec![2], get_task(3, &test_db).unwrap().blocked_by);
+        assert_eq!(1, get_task(1, &test_db).unwrap().subtasks_total);
+
+        let conn = open_existing(&test_db).unwrap();
+        let reminders: u32 = conn
+            .query_row("SELECT COUNT(*) FROM reminders", params![], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(1, reminders);
+
+        let history = history::task_history(2, &test_db).unwrap();
+        assert_eq!(
+            Some(EventAction::Undo),
+            history.last().map(|event| event.action)
+        );
+    }
+
+    #[test]
+    fn undo_steps_back_through_operations() {
+        let test_db = test_database("undo_steps_back_through_operations");
+
+        add("Buy milk", None, &test_db);
+        let changes = TaskChanges {
+            task: Some(String::from("Buy oat milk")),
+            ..TaskChanges::default()
+        };
+        update_task(1, &changes, &test_db).unwrap();
+        archive_task(1, SubtaskAction::Refuse, &test_db).unwrap();
+
+        assert_eq!(1, undo(1, &test_db).unwrap().len());
+        assert!(get_all_archived_tasks(&test_db).unwrap().is_empty());
+        assert_eq!("Buy oat milk", get_task(1, &test_db).unwrap().task);
+
+        assert_eq!(2, undo(5, &test_db).unwrap().len());
+        assert!(get_task(1, &test_db).is_err());
+
+        let conn = open_existing(&test_db).unwrap();
+        let tags: u32 = conn
+            .query_row("SELECT COUNT(*) FROM tags", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(0, tags);
+
+        assert!(undo(1, &test_db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn operations_that_change_nothing_are_not_kept() {
+        let test_db = test_database("operations_that_change_nothing_are_not_kept");
+
+        add("Buy milk", None, &test_db);
+        update_task(1, &TaskChanges::default(), &test_db).unwrap();
+
+        let undone = undo(1, &test_db).unwrap();
+        assert_eq!(EventAction::Create, undone[0].events[0].action);
+    }
+
+    #[test]
+    fn ending_a_series_is_undone_before_earlier_edits() {
+        let test_db = test_database("ending_a_series_is_undone_before_earlier_edits");
+
+        TodoData {
+            project: String::from("Work"),
+            task: String::from("Standup"),
+            due_date: "2999-01-01".parse().unwrap(),
+            repeat: Some(crate::recurrence::parse_recurrence("weekly").unwrap()),
+            ..TodoData::default()
+        }
+        .write_data(&test_db)
+        .expect("database does not exist");
+        let changes = TaskChanges {
+            priority: Some(Priority::High),
+            ..TaskChanges::default()
+        };
+        update_task(1, &changes, &test_db).unwrap();
+        end_series(1, &test_db).unwrap();
+        assert!(get_series(&test_db).unwrap().is_empty());
+
+        let undone = undo(1, &test_db).unwrap();
+        assert!(undone[0].events.is_empty());
+        assert_eq!(1, get_series(&test_db).unwrap().len());
+
+        undo(1, &test_db).unwrap();
+        assert_eq!(Priority::Medium, get_task(1, &test_db).unwrap().priority);
+        assert_eq!(Some(1), get_series(&test_db).unwrap()[0].task_id);
+    }
+}
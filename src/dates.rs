@@ -0,0 +1,338 @@
+use chrono::{
+    Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Weekday,
+};
+
+use crate::error::TodoError;
+
+/// Parses a date given on the command line, resolving relative expressions against today's
+/// local date
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a date or a known date expression
+pub fn parse_date(input: &str) -> Result<NaiveDate, TodoError> {
+    parse_date_expr(input, Local::now().date_naive())
+}
+
+/// Parses a date or a date expression relative to `today`
+///
+/// Accepted forms:
+/// - `YYYY-MM-DD`
+/// - `today`, `tomorrow`, `yesterday`
+/// - offsets such as `+3d`, `+2w`, `+1m`, `+1y` or `-1d`
+/// - weekday names (`fri`, `friday`, `next fri`), resolved to the next such day after today
+/// - `eow`, `eom` and `eoy` for the end of the current week, month and year
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a date or a known date expression
+pub fn parse_date_expr(input: &str, today: NaiveDate) -> Result<NaiveDate, TodoError> {
+    let expr = input.trim().to_lowercase();
+
+    if let Ok(date) = NaiveDate::parse_from_str(&expr, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let resolved = match expr.as_str() {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        "yesterday" => today.pred_opt(),
+        "eow" => end_of_week(today),
+        "eom" => end_of_month(today),
+        "eoy" => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        _ => parse_offset(&expr, today).or_else(|| {
+            parse_weekday(expr.strip_prefix("next ").unwrap_or(&expr))
+                .and_then(|weekday| next_weekday(today, weekday))
+        }),
+    };
+
+    resolved.ok_or_else(|| {
+        TodoError::InvalidDate(format!(
+            "'{input}' is not a date, use 'YYYY-MM-DD' or an expression like \
+             'tomorrow', '+3d', '+2w', 'fri' or 'eow'"
+        ))
+    })
+}
+
+/// Time of day used when a reminder is given as a date without a time
+pub const DEFAULT_REMINDER_TIME: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(time) => time,
+    None => panic!("09:00 is a valid time"),
+};
+
+/// Parses a date and time given on the command line, resolving relative expressions against
+/// the local time
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a date and time or a known expression
+pub fn parse_datetime(input: &str) -> Result<NaiveDateTime, TodoError> {
+    parse_datetime_expr(input, Local::now().naive_local())
+}
+
+/// Parses a date and time relative to `now`
+///
+/// Accepted forms are a date or date expression optionally followed by a time, e.g.
+/// `2024-06-14 17:30`, `tomorrow 8:00` or `fri`, which means 09:00, and offsets from now
+/// such as `+2h` or `+30min`.
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a date and time or a known expression
+pub fn parse_datetime_expr(input: &str, now: NaiveDateTime) -> Result<NaiveDateTime, TodoError> {
+    let expr = input.trim().to_lowercase();
+
+    if let Some(offset) = expr.strip_prefix('+').and_then(parse_time_offset) {
+        return now
+            .checked_add_signed(offset)
+            .ok_or_else(|| TodoError::InvalidDate(format!("'{input}' is too far in the future")));
+    }
+
+    let (date, time) = match expr.rsplit_once([' ', 't']) {
+        Some((date, time)) if time.contains(':') => (date, Some(time)),
+        _ => (expr.as_str(), None),
+    };
+
+    let time = match time {
+        Some(time) => NaiveTime::parse_from_str(time, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+            .map_err(|_| TodoError::InvalidDate(format!("'{time}' is not a time, use 'HH:MM'")))?,
+        None => DEFAULT_REMINDER_TIME,
+    };
+
+    Ok(parse_date_expr(date, now.date())?.and_time(time))
+}
+
+/// Parses an age given on the command line into the date that long before today's local
+/// date
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a known age
+pub fn parse_age(input: &str) -> Result<NaiveDate, TodoError> {
+    parse_age_expr(input, Local::now().date_naive())
+}
+
+/// Parses an age in days, weeks, months or years, e.g. `30d`, `2w`, `6m` or `1y`, into the
+/// date that long before `today`
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a known age
+pub fn parse_age_expr(input: &str, today: NaiveDate) -> Result<NaiveDate, TodoError> {
+    let expr = input.trim().to_lowercase();
+
+    expr.starts_with(|c: char| c.is_ascii_digit())
+        .then(|| parse_offset(&format!("-{expr}"), today))
+        .flatten()
+        .ok_or_else(|| {
+            TodoError::InvalidDate(format!(
+                "'{input}' is not an age, use a number followed by 'd', 'w', 'm' or 'y' \
+                 e.g. '30d' or '1y'"
+            ))
+        })
+}
+
+/// Parses how long before something happens, e.g. `1d`, `2h`, `1w` or `30min`
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidDate` if the input is not a known duration
+pub fn parse_duration(input: &str) -> Result<TimeDelta, TodoError> {
+    parse_time_offset(&input.trim().to_lowercase()).ok_or_else(|| {
+        TodoError::InvalidDate(format!(
+            "'{input}' is not a duration, use a number followed by 'min', 'h', 'd' or 'w' \
+             e.g. '30min' or '1d'"
+        ))
+    })
+}
+
+/// Parses durations in minutes, hours, days or weeks
+fn parse_time_offset(expr: &str) -> Option<TimeDelta> {
+    let split = expr.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = expr[..split].parse().ok()?;
+
+    match &expr[split..] {
+        "min" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Parses offsets such as `+3d`, `-1w`, `+2m` or `-1y` relative to `today`
+fn parse_offset(expr: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let (forward, rest) = if let Some(rest) = expr.strip_prefix('+') {
+        (true, rest)
+    } else {
+        (false, expr.strip_prefix('-')?)
+    };
+
+    let unit = rest.chars().last()?;
+    let amount: u32 = rest[..rest.len() - unit.len_utf8()].parse().ok()?;
+
+    match (unit, forward) {
+        ('d', true) => today.checked_add_days(Days::new(amount.into())),
+        ('d', false) => today.checked_sub_days(Days::new(amount.into())),
+        ('w', true) => today.checked_add_days(Days::new(u64::from(amount) * 7)),
+        ('w', false) => today.checked_sub_days(Days::new(u64::from(amount) * 7)),
+        ('m', true) => today.checked_add_months(Months::new(amount)),
+        ('m', false) => today.checked_sub_months(Months::new(amount)),
+        ('y', true) => today.checked_add_months(Months::new(amount.checked_mul(12)?)),
+        ('y', false) => today.checked_sub_months(Months::new(amount.checked_mul(12)?)),
+        _ => None,
+    }
+}
+
+/// Parses full or abbreviated weekday names
+fn parse_weekday(name: &str) -> Option<Weekday> {
+    match name {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// The next `weekday` strictly after `today`
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+    let days_ahead =
+        (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday() - 1) % 7 + 1;
+
+    today.checked_add_days(Days::new(days_ahead.into()))
+}
+
+/// The Sunday ending the current ISO week, today if it is already Sunday
+fn end_of_week(today: NaiveDate) -> Option<NaiveDate> {
+    today.checked_add_days(Days::new(
+        (6 - today.weekday().num_days_from_monday()).into(),
+    ))
+}
+
+/// The last day of the current month
+fn end_of_month(today: NaiveDate) -> Option<NaiveDate> {
+    today
+        .with_day(1)?
+        .checked_add_months(Months::new(1))?
+        .pred_opt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 12).unwrap()
+    }
+
+    fn parse(input: &str) -> NaiveDate {
+        parse_date_expr(input, today()).unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_iso_dates() {
+        assert_eq!(date("2024-06-14"), parse("2024-06-14"));
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(date("2024-06-12"), parse("today"));
+        assert_eq!(date("2024-06-13"), parse("Tomorrow"));
+        assert_eq!(date("2024-06-11"), parse("yesterday"));
+        assert_eq!(date("2024-06-16"), parse("eow"));
+        assert_eq!(date("2024-06-30"), parse("eom"));
+        assert_eq!(date("2024-12-31"), parse("eoy"));
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(date("2024-06-15"), parse("+3d"));
+        assert_eq!(date("2024-06-26"), parse("+2w"));
+        assert_eq!(date("2024-07-12"), parse("+1m"));
+        assert_eq!(date("2024-06-11"), parse("-1d"));
+        assert_eq!(date("2025-06-12"), parse("+1y"));
+    }
+
+    #[test]
+    fn parses_ages() {
+        let age = |input| parse_age_expr(input, today());
+
+        assert_eq!(date("2024-05-13"), age("30d").unwrap());
+        assert_eq!(date("2023-12-12"), age("6M").unwrap());
+        assert_eq!(date("2023-06-12"), age("1y").unwrap());
+        assert!(age("-1y").is_err());
+        assert!(age("1h").is_err());
+    }
+
+    #[test]
+    fn parses_weekdays() {
+        assert_eq!(date("2024-06-14"), parse("fri"));
+        assert_eq!(date("2024-06-14"), parse("next friday"));
+        assert_eq!(date("2024-06-17"), parse("mon"));
+        // The same weekday as today means next week
+        assert_eq!(date("2024-06-19"), parse("wed"));
+    }
+
+    #[test]
+    fn end_of_week_on_sunday_is_today() {
+        let sunday = date("2024-06-16");
+        assert_eq!(sunday, parse_date_expr("eow", sunday).unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_expressions() {
+        for input in ["someday", "+3x", "+d", "14/06/2024", "2024-02-30", "next"] {
+            assert!(matches!(
+                parse_date_expr(input, today()),
+                Err(TodoError::InvalidDate(_))
+            ));
+        }
+    }
+
+    fn now() -> NaiveDateTime {
+        today().and_hms_opt(14, 5, 0).unwrap()
+    }
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn parses_datetimes() {
+        let parse = |input| parse_datetime_expr(input, now()).unwrap();
+
+        assert_eq!(datetime("2024-06-14 17:30"), parse("2024-06-14 17:30"));
+        assert_eq!(datetime("2024-06-14 17:30"), parse("2024-06-14T17:30"));
+        assert_eq!(datetime("2024-06-13 08:00"), parse("tomorrow 8:00"));
+        assert_eq!(datetime("2024-06-14 09:00"), parse("next fri"));
+        assert_eq!(datetime("2024-06-12 16:05"), parse("+2h"));
+        assert_eq!(datetime("2024-06-12 14:35"), parse("+30min"));
+
+        for input in ["someday 10:00", "tomorrow 25:00", "+2x"] {
+            assert!(matches!(
+                parse_datetime_expr(input, now()),
+                Err(TodoError::InvalidDate(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(TimeDelta::days(1), parse_duration("1d").unwrap());
+        assert_eq!(TimeDelta::hours(2), parse_duration("2H").unwrap());
+        assert_eq!(TimeDelta::minutes(30), parse_duration("30min").unwrap());
+        assert_eq!(TimeDelta::weeks(1), parse_duration("1w").unwrap());
+        assert!(parse_duration("1m").is_err());
+        assert!(parse_duration("d").is_err());
+    }
+}
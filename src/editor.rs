@@ -0,0 +1,134 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{self, Command},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Names tried for the temporary file before giving up
+const TEMP_FILE_ATTEMPTS: u32 = 100;
+
+/// Gets new text for a task, from stdin when it is piped and from an editor otherwise
+///
+/// The editor opens on a temporary file holding `current`, so existing text can be
+/// changed rather than typed again.
+///
+/// # Errors
+///
+/// Returns an error if stdin or the temporary file can't be read or written, or the editor
+/// can't be started or exits unsuccessfully
+pub fn read_text(current: &str) -> io::Result<String> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() {
+        return edit_text(current);
+    }
+
+    let mut text = String::new();
+    stdin.read_to_string(&mut text)?;
+
+    Ok(text)
+}
+
+/// Opens `$VISUAL` or `$EDITOR` on a temporary file holding `current` and returns what the
+/// file holds once the editor exits
+///
+/// # Errors
+///
+/// Returns an error if the temporary file can't be written or read, or the editor can't be
+/// started or exits unsuccessfully
+pub fn edit_text(current: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_EDITOR));
+
+    let (path, mut file) = create_temp_file()?;
+    let written = file.write_all(current.as_bytes());
+    drop(file);
+    if let Err(err) = written {
+        let _ = fs::remove_file(&path);
+        return Err(err);
+    }
+
+    // Run through the shell so editors given with arguments, like 'code --wait', work
+    let status = if cfg!(windows) {
+        Command::new("cmd")
+            .args(["/C", &format!("{editor} \"{}\"", path.display())])
+            .status()
+    } else {
+        Command::new("sh")
+            .args(["-c", &format!("{editor} \"$1\""), "sh"])
+            .arg(&path)
+            .status()
+    };
+
+    let text = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!("editor failed ({status})"))),
+        Err(err) => Err(err),
+    };
+    let _ = fs::remove_file(&path);
+
+    text
+}
+
+/// Creates a new temporary file only the current user can read and write
+///
+/// The file must not exist yet, so a file or link planted under the same name by someone
+/// else on a shared machine is never written through. Taken names are skipped.
+fn create_temp_file() -> io::Result<(PathBuf, File)> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+
+    for attempt in 0..TEMP_FILE_ATTEMPTS {
+        let path = env::temp_dir().join(format!(
+            "todo-note-{}-{nanos:x}-{attempt}.md",
+            process::id()
+        ));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "couldn't find a free name for the temporary notes file",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_files_are_new_and_private() {
+        let (first, _) = create_temp_file().unwrap();
+        let (second, _) = create_temp_file().unwrap();
+        assert_ne!(first, second);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+}
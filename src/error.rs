@@ -0,0 +1,164 @@
+use std::{fmt, io};
+
+use rusqlite::ErrorCode;
+
+/// Result type used throughout the crate
+pub type Result<T, E = TodoError> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while handling a command
+#[derive(Debug)]
+pub enum TodoError {
+    /// The database file does not exist yet
+    MissingDatabase(String),
+
+    /// The database file exists but is not a readable todo database
+    CorruptDatabase(rusqlite::Error),
+
+    /// No task with the given id
+    TaskNotFound(u64),
+
+    /// No recurring series with the given id
+    SeriesNotFound(u64),
+
+    /// A date could not be understood
+    InvalidDate(String),
+
+    /// A repeat rule could not be understood
+    InvalidRepeat(String),
+
+    /// A list of task ids could not be understood
+    InvalidIds(String),
+
+    /// The data breaks one of the database constraints
+    ConstraintViolation(String),
+
+    /// A task with subtasks was about to be removed without saying what to do with them
+    HasSubtasks(u64),
+
+    /// Making the first task depend on the second would create a dependency cycle
+    DependencyCycle(u64, u64),
+
+    /// Any other database failure
+    Database(rusqlite::Error),
+
+    /// Reading or writing a file failed
+    Io(io::Error),
+}
+
+impl TodoError {
+    /// Process exit code for the error, so scripts can tell failures apart
+    ///
+    /// Code 2 is left to clap for invalid command line usage, which includes dates, ids and
+    /// repeat rules rejected by their value parsers.
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::TaskNotFound(_) | Self::SeriesNotFound(_) => 3,
+            Self::MissingDatabase(_) => 4,
+            Self::CorruptDatabase(_) => 5,
+            Self::InvalidDate(_) | Self::InvalidRepeat(_) | Self::InvalidIds(_) => 6,
+            Self::ConstraintViolation(_) | Self::HasSubtasks(_) | Self::DependencyCycle(..) => 7,
+            Self::Database(_) => 8,
+            Self::Io(_) => 9,
+        }
+    }
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingDatabase(path) => {
+                write!(f, "no database found at {path}, add a task to create it")
+            }
+            Self::CorruptDatabase(err) => {
+                write!(f, "the database is corrupt or not a todo database ({err})")
+            }
+            Self::TaskNotFound(id) => write!(f, "task {id} doesn't exist"),
+            Self::SeriesNotFound(id) => write!(f, "recurring series {id} doesn't exist"),
+            Self::InvalidDate(message) => write!(f, "invalid date: {message}"),
+            Self::InvalidRepeat(message) => write!(f, "invalid repeat rule: {message}"),
+            Self::InvalidIds(message) => write!(f, "invalid task ids: {message}"),
+            Self::ConstraintViolation(message) => write!(f, "invalid task data: {message}"),
+            Self::HasSubtasks(id) => write!(
+                f,
+                "task {id} has subtasks, pass --subtasks cascade or --subtasks reparent"
+            ),
+            Self::DependencyCycle(task, prerequisite) if task == prerequisite => {
+                write!(f, "task {task} can't depend on itself")
+            }
+            Self::DependencyCycle(task, prerequisite) => write!(
+                f,
+                "task {task} can't depend on task {prerequisite}, which already depends on it"
+            ),
+            Self::Database(err) => write!(f, "database error: {err}"),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TodoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CorruptDatabase(err) | Self::Database(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for TodoError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::SqliteFailure(failure, message)
+                if failure.code == ErrorCode::ConstraintViolation =>
+            {
+                Self::ConstraintViolation(message.unwrap_or_else(|| failure.to_string()))
+            }
+            rusqlite::Error::SqliteFailure(failure, _)
+                if matches!(
+                    failure.code,
+                    ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase
+                ) =>
+            {
+                Self::CorruptDatabase(err)
+            }
+            err => Self::Database(err),
+        }
+    }
+}
+
+impl From<io::Error> for TodoError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    #[test]
+    fn constraint_failures_are_mapped() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER NOT NULL)", ())
+            .unwrap();
+
+        let err: TodoError = conn
+            .execute("INSERT INTO t (x) VALUES (NULL)", ())
+            .unwrap_err()
+            .into();
+
+        assert!(matches!(err, TodoError::ConstraintViolation(_)));
+        assert_eq!(7, err.exit_code());
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let not_found = TodoError::TaskNotFound(42);
+        let corrupt = TodoError::CorruptDatabase(rusqlite::Error::InvalidQuery);
+
+        assert_eq!("task 42 doesn't exist", not_found.to_string());
+        assert_ne!(not_found.exit_code(), corrupt.exit_code());
+    }
+}
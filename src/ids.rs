@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use crate::error::TodoError;
+
+/// Most ids a single range may cover, so a typo like `1-100000` doesn't select everything
+const MAX_RANGE_LEN: u64 = 10_000;
+
+/// A task id or an inclusive range of them, as in `3` or `9-14`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+/// Parses a task id or a range of ids given on the command line
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidIds` if the input is not an id or a range, or the range is
+/// backwards or too long
+pub fn parse_id_range(input: &str) -> Result<IdRange, TodoError> {
+    let invalid = || {
+        TodoError::InvalidIds(format!(
+            "'{input}' is not a task id or a range, use e.g. '3' or '9-14'"
+        ))
+    };
+    let id = |text: &str| text.trim().parse::<u64>().map_err(|_| invalid());
+
+    let (first, last) = match input.split_once('-') {
+        Some((first, last)) => (id(first)?, id(last)?),
+        None => (id(input)?, id(input)?),
+    };
+
+    if first > last {
+        return Err(TodoError::InvalidIds(format!(
+            "'{input}' runs backwards, use '{last}-{first}'"
+        )));
+    }
+    if last - first >= MAX_RANGE_LEN {
+        return Err(TodoError::InvalidIds(format!(
+            "'{input}' covers more than {MAX_RANGE_LEN} tasks"
+        )));
+    }
+
+    Ok(IdRange { first, last })
+}
+
+/// Expands ranges into the ids they cover in the order given, leaving out repeats
+#[must_use]
+pub fn expand_ids(ranges: &[IdRange]) -> Vec<u64> {
+    let mut seen = HashSet::new();
+
+    ranges
+        .iter()
+        .flat_map(|range| range.first..=range.last)
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ids_and_ranges() {
+        assert_eq!(IdRange { first: 3, last: 3 }, parse_id_range("3").unwrap());
+        assert_eq!(
+            IdRange { first: 9, last: 14 },
+            parse_id_range("9-14").unwrap()
+        );
+        assert!(matches!(
+            parse_id_range("14-9"),
+            Err(TodoError::InvalidIds(_))
+        ));
+        assert!(parse_id_range("3-").is_err());
+        assert!(parse_id_range("x").is_err());
+        assert!(parse_id_range("1-100000").is_err());
+    }
+
+    #[test]
+    fn expands_ranges_in_order_without_repeats() {
+        let ranges: Vec<IdRange> = ["9-11", "3", "10-12"]
+            .into_iter()
+            .map(|input| parse_id_range(input).unwrap())
+            .collect();
+
+        assert_eq!(vec![9, 10, 11, 3, 12], expand_ids(&ranges));
+    }
+}
@@ -4,4 +4,10 @@ extern crate prettytable;
 pub mod args;
 pub mod data_handler;
 pub mod database;
+pub mod dates;
+pub mod editor;
+pub mod error;
+pub mod ids;
+pub mod notify;
+pub mod recurrence;
 pub mod views;
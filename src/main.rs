@@ -2,12 +2,35 @@ use clap::Parser;
 use todo::{
     args::{Cli, OptionTypes},
     data_handler,
+    error::TodoError,
 };
 
 fn main() {
     let cli = Cli::parse();
+    if let Err(err) = cli.check() {
+        err.exit();
+    }
+
+    if let Err(err) = run(cli) {
+        eprintln!("Error: {err}");
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(cli: Cli) -> Result<(), TodoError> {
+    let db_path = data_handler::resolve_db_path(cli.db)?;
 
     match cli.options {
-        OptionTypes::Task(more_options) => data_handler::handle_data(more_options.command),
+        OptionTypes::Task(more_options) => {
+            data_handler::handle_data(more_options.command, &db_path, cli.format, cli.timestamps)
+        }
+        OptionTypes::Remind(remind) => {
+            data_handler::handle_reminders(remind.command, &db_path, cli.format, cli.timestamps)
+        }
+        OptionTypes::Log(log) => data_handler::handle_log(&log, &db_path, cli.format),
+        OptionTypes::Undo(undo) => data_handler::handle_undo(&undo, &db_path),
+        OptionTypes::Archive(archive) => {
+            data_handler::handle_archived(archive.command, &db_path, cli.format, cli.timestamps)
+        }
     }
 }
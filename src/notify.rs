@@ -0,0 +1,110 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crate::database::Reminder;
+
+/// Runs the notification command for a reminder that has gone off
+///
+/// The command runs through the shell with the task in `TODO_TASK_ID`, `TODO_TASK`,
+/// `TODO_PROJECT`, `TODO_DUE_DATE`, `TODO_PRIORITY` and `TODO_REMIND_AT`, and as a JSON
+/// object on stdin.
+///
+/// # Errors
+///
+/// Returns an error if the command can't be started or exits unsuccessfully
+///
+/// # Panics
+///
+/// This function will panic if serialisation fails, which plain task data never does
+pub fn run_notify_command(command: &str, reminder: &Reminder) -> io::Result<()> {
+    let task = &reminder.task;
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .env("TODO_TASK_ID", task.id.to_string())
+        .env("TODO_TASK", &task.task)
+        .env("TODO_PROJECT", &task.project)
+        .env(
+            "TODO_DUE_DATE",
+            task.due_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+        )
+        .env("TODO_PRIORITY", task.priority.to_string())
+        .env("TODO_REMIND_AT", reminder.remind_at.to_string())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let json = serde_json::to_string(task).expect("tasks always serialise to JSON");
+
+        // Commands that don't read stdin close it early, that isn't a failure
+        match writeln!(stdin, "{json}") {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+            _ => {}
+        }
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "notify command failed ({status})"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TodoView;
+    use chrono::NaiveDate;
+
+    fn reminder() -> Reminder {
+        Reminder {
+            id: 1,
+            remind_at: NaiveDate::from_ymd_opt(2024, 6, 13)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap(),
+            task: TodoView {
+                id: 7,
+                project: String::from("Work"),
+                task: String::from("Send report"),
+                ..TodoView::default()
+            },
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_gets_task_in_env_and_stdin() {
+        let out = std::env::temp_dir().join("todo-test-notify.txt");
+        let command = format!(
+            "echo \"$TODO_TASK_ID $TODO_TASK $TODO_REMIND_AT\" > '{0}' && cat >> '{0}'",
+            out.display()
+        );
+
+        run_notify_command(&command, &reminder()).unwrap();
+
+        let written = std::fs::read_to_string(&out).unwrap();
+        let mut lines = written.lines();
+        assert_eq!(Some("7 Send report 2024-06-13 09:00:00"), lines.next());
+        let json: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!("Send report", json["task"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_command_is_an_error() {
+        assert!(run_notify_command("exit 3", &reminder()).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt;
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Serialize, Serializer};
+
+use crate::error::TodoError;
+
+/// Unit of a repeat interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+/// How often a recurring task comes back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recurrence {
+    /// Every `interval` days, weeks or months
+    Every { interval: u32, period: Period },
+    /// Monday to Friday
+    Weekdays,
+}
+
+/// Parses a repeat rule given on the command line
+///
+/// Accepted forms are `daily`, `weekly`, `monthly`, `weekdays` and intervals such as
+/// `every 2w`, `every 3d` or `every 6m`.
+///
+/// # Errors
+///
+/// Returns `TodoError::InvalidRepeat` if the input is not a known repeat rule
+pub fn parse_recurrence(input: &str) -> Result<Recurrence, TodoError> {
+    let rule = input.trim().to_lowercase();
+
+    let every = |period| {
+        Some(Recurrence::Every {
+            interval: 1,
+            period,
+        })
+    };
+    let recurrence = match rule.as_str() {
+        "daily" => every(Period::Day),
+        "weekly" => every(Period::Week),
+        "monthly" => every(Period::Month),
+        "weekdays" => Some(Recurrence::Weekdays),
+        _ => rule
+            .strip_prefix("every")
+            .and_then(|interval| parse_interval(interval.trim())),
+    };
+
+    recurrence.ok_or_else(|| {
+        TodoError::InvalidRepeat(format!(
+            "'{input}' is not a repeat rule, use 'daily', 'weekly', 'monthly', 'weekdays' \
+             or an interval like 'every 2w'"
+        ))
+    })
+}
+
+/// Parses intervals such as `2w`, `3d` or `6m`
+fn parse_interval(interval: &str) -> Option<Recurrence> {
+    let unit = interval.chars().last()?;
+    let amount: u32 = interval[..interval.len() - unit.len_utf8()].parse().ok()?;
+
+    let period = match unit {
+        'd' => Period::Day,
+        'w' => Period::Week,
+        'm' => Period::Month,
+        _ => return None,
+    };
+
+    (amount > 0).then_some(Recurrence::Every {
+        interval: amount,
+        period,
+    })
+}
+
+impl Recurrence {
+    /// Due date of the occurrence after one due on `due`, never earlier than tomorrow
+    ///
+    /// Occurrences missed while a task was overdue are skipped, the schedule keeps its
+    /// original rhythm e.g. a weekly Friday task stays on Fridays.
+    #[must_use]
+    pub fn next_due(self, due: NaiveDate, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Self::Every { interval, period } => (1..)
+                .map_while(|step: u32| step.checked_mul(interval))
+                .map(|amount| match period {
+                    Period::Day => due.checked_add_days(Days::new(amount.into())),
+                    Period::Week => due.checked_add_days(Days::new(u64::from(amount) * 7)),
+                    Period::Month => due.checked_add_months(Months::new(amount)),
+                })
+                .find(|next| next.is_none_or(|next| next > today))
+                .flatten(),
+            Self::Weekdays => {
+                let mut next = due.max(today).succ_opt()?;
+                while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+                    next = next.succ_opt()?;
+                }
+
+                Some(next)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Every {
+                interval: 1,
+                period,
+            } => f.write_str(match period {
+                Period::Day => "daily",
+                Period::Week => "weekly",
+                Period::Month => "monthly",
+            }),
+            Self::Every { interval, period } => {
+                let unit = match period {
+                    Period::Day => 'd',
+                    Period::Week => 'w',
+                    Period::Month => 'm',
+                };
+
+                write!(f, "every {interval}{unit}")
+            }
+            Self::Weekdays => f.write_str("weekdays"),
+        }
+    }
+}
+
+impl Serialize for Recurrence {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl ToSql for Recurrence {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for Recurrence {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        parse_recurrence(value.as_str()?).map_err(|err| FromSqlError::Other(Box::new(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(
+            Recurrence::Every {
+                interval: 1,
+                period: Period::Week
+            },
+            parse_recurrence("Weekly").unwrap()
+        );
+        assert_eq!(
+            Recurrence::Every {
+                interval: 2,
+                period: Period::Week
+            },
+            parse_recurrence("every 2w").unwrap()
+        );
+        assert_eq!(Recurrence::Weekdays, parse_recurrence("weekdays").unwrap());
+
+        for input in ["sometimes", "every", "every 0d", "every 2y", "every w"] {
+            assert!(matches!(
+                parse_recurrence(input),
+                Err(TodoError::InvalidRepeat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rules_round_trip_through_display() {
+        for rule in [
+            "daily", "weekly", "monthly", "weekdays", "every 3d", "every 6m",
+        ] {
+            assert_eq!(rule, parse_recurrence(rule).unwrap().to_string());
+        }
+        assert_eq!("daily", parse_recurrence("every 1d").unwrap().to_string());
+    }
+
+    #[test]
+    fn next_due_follows_the_rule() {
+        // A Wednesday
+        let today = date("2024-06-12");
+        let next = |rule: &str, due: &str| {
+            parse_recurrence(rule)
+                .unwrap()
+                .next_due(date(due), today)
+                .unwrap()
+        };
+
+        // Done ahead of time, the next one is a full interval later
+        assert_eq!(date("2024-06-21"), next("weekly", "2024-06-14"));
+        assert_eq!(date("2024-07-15"), next("monthly", "2024-06-15"));
+        assert_eq!(date("2024-06-13"), next("daily", "2024-06-12"));
+
+        // Done late, missed occurrences are skipped but the rhythm is kept
+        assert_eq!(date("2024-06-14"), next("weekly", "2024-05-31"));
+        assert_eq!(date("2024-06-19"), next("every 2w", "2024-05-22"));
+
+        // Weekdays skip the weekend
+        assert_eq!(date("2024-06-17"), next("weekdays", "2024-06-14"));
+        assert_eq!(date("2024-06-13"), next("weekdays", "2024-06-03"));
+    }
+}
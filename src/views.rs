@@ -1,52 +1,765 @@
-use crate::database::TodoView;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
 
-use prettytable::Table;
+use crate::database::{history::TaskEvent, Priority, Series, TodoView};
+use chrono::{Local, NaiveDateTime};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
 
-/// Shows task data in a formatted table
+use prettytable::{Cell, Row, Table};
+
+/// Output formats for listings and stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Formatted table for people
+    #[default]
+    Table,
+    /// JSON array of tasks, or an object of stats
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// One line per task, for status bars and simple scripts
+    Plain,
+}
+
+impl OutputFormat {
+    /// Gets the output implementation for the format, `timestamps` adds the task timestamps
+    /// to tables, other formats always include them
+    #[must_use]
+    pub fn output(self, timestamps: bool) -> Box<dyn Output> {
+        match self {
+            Self::Table => Box::new(TableOutput { timestamps }),
+            Self::Json => Box::new(JsonOutput),
+            Self::Csv => Box::new(DelimitedOutput { separator: ',' }),
+            Self::Tsv => Box::new(DelimitedOutput { separator: '\t' }),
+            Self::Plain => Box::new(PlainOutput),
+        }
+    }
+}
+
+/// Prints listings and stats in one output format
+pub trait Output {
+    /// Prints a list of tasks
+    fn print_tasks(&self, tasks: Vec<TodoView>);
+
+    /// Prints a single task in full, notes included
+    fn print_task(&self, task: TodoView);
+
+    /// Prints named stats, in the order given
+    fn print_stats(&self, stats: &[(&str, StatValue)]);
+
+    /// Prints recurring series
+    fn print_series(&self, series: Vec<Series>);
+
+    /// Prints task history events in the order given
+    fn print_events(&self, events: Vec<TaskEvent>);
+
+    /// Prints headings and messages meant for people, machine readable formats skip them
+    fn print_notice(&self, _message: &str) {}
+}
+
+/// A single stat, counts stay whole numbers in every format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatValue {
+    Count(u32),
+    /// A number of days, `None` when there is nothing to measure
+    Days(Option<f64>),
+}
+
+impl fmt::Display for StatValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count(count) => write!(f, "{count}"),
+            Self::Days(Some(days)) => write!(f, "{days:.1}"),
+            Self::Days(None) => f.write_str("-"),
+        }
+    }
+}
+
+impl From<StatValue> for Value {
+    fn from(stat: StatValue) -> Self {
+        match stat {
+            StatValue::Count(count) => Self::from(count),
+            // Rounded like the other formats show it
+            StatValue::Days(days) => {
+                days.map_or(Self::Null, |days| Self::from((days * 10.0).round() / 10.0))
+            }
+        }
+    }
+}
+
+/// Formatted table, the default output
+pub struct TableOutput {
+    /// Add created, updated and completed columns
+    pub timestamps: bool,
+}
+
+impl Output for TableOutput {
+    fn print_tasks(&self, tasks: Vec<TodoView>) {
+        show_data(tasks, self.timestamps).printstd();
+    }
+
+    fn print_task(&self, task: TodoView) {
+        show_task(&task).printstd();
+    }
+
+    fn print_stats(&self, stats: &[(&str, StatValue)]) {
+        // A single stat stays a bare number, as status bars and scripts have always read it
+        if let [(_, value)] = stats {
+            println!("{value}");
+            return;
+        }
+
+        let mut table = Table::new();
+        table.add_row(Row::new(
+            stats
+                .iter()
+                .map(|(name, _)| Cell::new(&name.replace('_', " ").to_uppercase()))
+                .collect(),
+        ));
+        table.add_row(Row::new(
+            stats
+                .iter()
+                .map(|(_, count)| Cell::new(&count.to_string()))
+                .collect(),
+        ));
+        table.printstd();
+    }
+
+    fn print_series(&self, series: Vec<Series>) {
+        show_series(series).printstd();
+    }
+
+    fn print_events(&self, events: Vec<TaskEvent>) {
+        show_events(&events).printstd();
+    }
+
+    fn print_notice(&self, message: &str) {
+        println!("{message}");
+    }
+}
+
+/// JSON for scripts
+pub struct JsonOutput;
+
+impl Output for JsonOutput {
+    fn print_tasks(&self, tasks: Vec<TodoView>) {
+        println!("{}", tasks_to_json(&tasks));
+    }
+
+    fn print_task(&self, task: TodoView) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&task).expect("tasks always serialise to JSON")
+        );
+    }
+
+    fn print_stats(&self, stats: &[(&str, StatValue)]) {
+        println!("{}", stats_to_json(stats));
+    }
+
+    fn print_series(&self, series: Vec<Series>) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&series).expect("series always serialise to JSON")
+        );
+    }
+
+    fn print_events(&self, events: Vec<TaskEvent>) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&events).expect("events always serialise to JSON")
+        );
+    }
+}
+
+/// CSV or TSV with a header row
+pub struct DelimitedOutput {
+    pub separator: char,
+}
+
+impl Output for DelimitedOutput {
+    fn print_tasks(&self, tasks: Vec<TodoView>) {
+        print!("{}", tasks_to_delimited(&tasks, self.separator));
+    }
+
+    fn print_task(&self, task: TodoView) {
+        self.print_tasks(vec![task]);
+    }
+
+    fn print_stats(&self, stats: &[(&str, StatValue)]) {
+        let header: Vec<String> = stats.iter().map(|(name, _)| (*name).to_owned()).collect();
+        let values: Vec<String> = stats.iter().map(|(_, count)| count.to_string()).collect();
+
+        println!("{}", delimited_line(&header, self.separator));
+        println!("{}", delimited_line(&values, self.separator));
+    }
+
+    fn print_series(&self, series: Vec<Series>) {
+        let header = [
+            "id", "repeat", "paused", "task_id", "project", "task", "due_date",
+        ]
+        .map(String::from);
+
+        println!("{}", delimited_line(&header, self.separator));
+        for row in series {
+            let fields = series_fields(row);
+            println!("{}", delimited_line(&fields, self.separator));
+        }
+    }
+
+    fn print_events(&self, events: Vec<TaskEvent>) {
+        print!("{}", events_to_delimited(&events, self.separator));
+    }
+}
+
+/// One line per task, and bare numbers for stats
+pub struct PlainOutput;
+
+impl Output for PlainOutput {
+    fn print_tasks(&self, tasks: Vec<TodoView>) {
+        for (depth, task) in task_tree(tasks) {
+            println!("{}{}", "  ".repeat(depth), task_to_plain(&task));
+        }
+    }
+
+    fn print_task(&self, task: TodoView) {
+        println!("{}", task_to_plain(&task));
+        if let Some(notes) = &task.notes {
+            println!("\n{}", notes.trim_end());
+        }
+    }
+
+    fn print_stats(&self, stats: &[(&str, StatValue)]) {
+        for (_, value) in stats {
+            println!("{value}");
+        }
+    }
+
+    fn print_series(&self, series: Vec<Series>) {
+        for row in series {
+            let state = if row.paused { " (paused)" } else { "" };
+            println!(
+                "{} {}{state}: {}",
+                row.id,
+                row.rule,
+                row.task.unwrap_or_default()
+            );
+        }
+    }
+
+    fn print_events(&self, events: Vec<TaskEvent>) {
+        for event in events {
+            println!(
+                "{} task {} {}: {}",
+                event.at.format("%Y-%m-%d %H:%M"),
+                event.task_id,
+                event.action,
+                event_changes(&event)
+            );
+        }
+    }
+}
+
+/// Serialises tasks as a JSON array
 ///
 /// # Panics
 ///
-/// This function will panic if date parsing fails for the fallback date
+/// This function will panic if serialisation fails, which plain task data never does
 #[must_use]
-pub fn show_data(data: Vec<TodoView>) -> Table {
-    let today: chrono::DateTime<Utc> = Utc::now();
+pub fn tasks_to_json(tasks: &[TodoView]) -> String {
+    serde_json::to_string_pretty(tasks).expect("tasks always serialise to JSON")
+}
+
+/// Serialises stats as a JSON object
+#[must_use]
+pub fn stats_to_json(stats: &[(&str, StatValue)]) -> String {
+    let object: Map<String, Value> = stats
+        .iter()
+        .map(|(name, value)| ((*name).to_owned(), Value::from(*value)))
+        .collect();
+
+    Value::Object(object).to_string()
+}
+
+/// Renders tasks as CSV or TSV with a header row
+#[must_use]
+pub fn tasks_to_delimited(tasks: &[TodoView], separator: char) -> String {
+    let header = [
+        "id",
+        "project",
+        "task",
+        "tags",
+        "priority",
+        "due_date",
+        "complete",
+        "archived",
+        "archived_at",
+        "parent_id",
+        "subtasks_done",
+        "subtasks_total",
+        "blocked_by",
+        "created_at",
+        "updated_at",
+        "completed_at",
+        "notes",
+    ]
+    .map(String::from);
+
+    let mut lines = vec![delimited_line(&header, separator)];
+    for task in tasks {
+        let fields = [
+            task.id.to_string(),
+            task.project.clone(),
+            task.task.clone(),
+            task.tags.join(","),
+            task.priority.to_string(),
+            task.due_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            task.complete.to_string(),
+            task.archived.to_string(),
+            timestamp(task.archived_at),
+            task.parent_id.map(|id| id.to_string()).unwrap_or_default(),
+            task.subtasks_done.to_string(),
+            task.subtasks_total.to_string(),
+            task.blocked_by
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            timestamp(task.created_at),
+            timestamp(task.updated_at),
+            timestamp(task.completed_at),
+            task.notes.clone().unwrap_or_default(),
+        ];
+        lines.push(delimited_line(&fields, separator));
+    }
+
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+/// Renders history events as CSV or TSV with a header row, values are JSON objects
+#[must_use]
+pub fn events_to_delimited(events: &[TaskEvent], separator: char) -> String {
+    let header = [
+        "id",
+        "task_id",
+        "task",
+        "action",
+        "old_values",
+        "new_values",
+        "at",
+    ]
+    .map(String::from);
+
+    let json = |state: &Option<_>| {
+        state
+            .clone()
+            .map(|state| Value::Object(state).to_string())
+            .unwrap_or_default()
+    };
+
+    let mut lines = vec![delimited_line(&header, separator)];
+    for event in events {
+        let fields = [
+            event.id.to_string(),
+            event.task_id.to_string(),
+            event.task.clone(),
+            event.action.to_string(),
+            json(&event.old_values),
+            json(&event.new_values),
+            event.at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ];
+        lines.push(delimited_line(&fields, separator));
+    }
+
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+/// Joins fields into one line, quoting CSV fields and flattening TSV fields as needed
+fn delimited_line(fields: &[String], separator: char) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if separator == '\t' {
+                field.replace(['\t', '\n', '\r'], " ")
+            } else if field.contains([separator, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+
+    fields.join(&separator.to_string())
+}
+
+/// Renders a task on a single line
+#[must_use]
+pub fn task_to_plain(task: &TodoView) -> String {
+    let mut line = format!(
+        "[{}] {} {}{}",
+        if task.complete { "x" } else { " " },
+        task.id,
+        task.task,
+        subtask_progress(task)
+    );
+
+    // Writing to a String can't fail
+    if let Some(due_date) = task.due_date {
+        let _ = write!(line, " (due {due_date})");
+    }
+    for tag in &task.tags {
+        let _ = write!(line, " +{tag}");
+    }
+    if is_blocked(task) {
+        line.push_str(" (blocked)");
+    }
+    if task.archived {
+        line.push_str(" (archived)");
+    }
+
+    line
+}
+
+/// Fields of a series row in table column order, empty when the series has no tasks left
+fn series_fields(series: Series) -> [String; 7] {
+    [
+        series.id.to_string(),
+        series.rule.to_string(),
+        series.paused.to_string(),
+        series.task_id.map(|id| id.to_string()).unwrap_or_default(),
+        series.project.unwrap_or_default(),
+        series.task.unwrap_or_default(),
+        series
+            .due_date
+            .map(|date| date.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+/// Shows recurring series in a formatted table
+#[must_use]
+pub fn show_series(series: Vec<Series>) -> Table {
+    let mut table = Table::new();
+    table.add_row(row![
+        "ID", "REPEAT", "PAUSED", "TASK ID", "PROJECT", "TASK", "DUE DATE"
+    ]);
+
+    for row in series {
+        table.add_row(Row::new(
+            series_fields(row)
+                .iter()
+                .map(|field| Cell::new(field))
+                .collect(),
+        ));
+    }
+
+    table
+}
+
+/// Shows history events in a formatted table
+#[must_use]
+pub fn show_events(events: &[TaskEvent]) -> Table {
     let mut table = Table::new();
-    table.add_row(row!["ID", "PROJECT", "TASK", "DUE DATE", "COMPLETE"]);
+    table.add_row(row!["AT", "TASK ID", "TASK", "ACTION", "CHANGES"]);
+
+    for event in events {
+        table.add_row(Row::new(vec![
+            Cell::new(&event.at.format("%Y-%m-%d %H:%M").to_string()),
+            Cell::new(&event.task_id.to_string()),
+            Cell::new(&event.task),
+            Cell::new(&event.action.to_string()),
+            Cell::new(&event_changes(event)),
+        ]));
+    }
+
+    table
+}
 
-    for row in data {
-        // use chrono to convert date string into a utc datetime type
-        let due_date = row.due_date.clone();
-        let naive_date = if let Ok(parsed_date) = NaiveDate::parse_from_str(&due_date, "%Y-%m-%d") {
-            parsed_date
+/// Describes what an event changed, e.g. `due_date: 2024-06-14 -> 2024-06-21`
+///
+/// Tasks that were added, removed or brought back list their values instead. Notes are too
+/// long to repeat, only whether they were added, edited or cleared is mentioned.
+#[must_use]
+pub fn event_changes(event: &TaskEvent) -> String {
+    match (event.action, &event.old_values, &event.new_values) {
+        (_, Some(old), Some(new)) => old
+            .iter()
+            .map(|(field, value)| {
+                let to = new.get(field).unwrap_or(&Value::Null);
+                match (field.as_str(), value, to) {
+                    ("notes", Value::Null, _) => String::from("notes added"),
+                    ("notes", _, Value::Null) => String::from("notes cleared"),
+                    ("notes", _, _) => String::from("notes edited"),
+                    _ => format!("{field}: {} -> {}", field_value(value), field_value(to)),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        (_, old, new) => new
+            .as_ref()
+            .or(old.as_ref())
+            .map(|state| {
+                state
+                    .iter()
+                    .filter(|(field, _)| !matches!(field.as_str(), "task" | "notes"))
+                    .map(|(field, value)| format!("{field}: {}", field_value(value)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Formats a recorded field value for people, e.g. tags as `a,b` and missing values as `none`
+fn field_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("none"),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => {
+            if items.is_empty() {
+                String::from("none")
+            } else {
+                items.iter().map(field_value).collect::<Vec<_>>().join(",")
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Arranges tasks so subtasks follow their parent, paired with their depth in the tree
+///
+/// Siblings keep their order from the listing. Tasks whose parent isn't listed are shown at
+/// the top level.
+#[must_use]
+pub fn task_tree(tasks: Vec<TodoView>) -> Vec<(usize, TodoView)> {
+    let positions: HashMap<(bool, u64), usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(index, task)| ((task.archived, task.id), index))
+        .collect();
+
+    let mut roots = Vec::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, task) in tasks.iter().enumerate() {
+        match task
+            .parent_id
+            .and_then(|parent_id| positions.get(&(task.archived, parent_id)))
+        {
+            Some(&parent) if parent != index => children.entry(parent).or_default().push(index),
+            _ => roots.push(index),
+        }
+    }
+
+    let mut order = Vec::with_capacity(tasks.len());
+    let mut visited = vec![false; tasks.len()];
+    let mut stack: Vec<(usize, usize)> = roots.into_iter().rev().map(|index| (0, index)).collect();
+    while let Some((depth, index)) = stack.pop() {
+        if std::mem::replace(&mut visited[index], true) {
+            continue;
+        }
+
+        order.push((depth, index));
+        if let Some(subtasks) = children.get(&index) {
+            stack.extend(subtasks.iter().rev().map(|&child| (depth + 1, child)));
+        }
+    }
+
+    // Parent links that loop never reach a root, list them at the top level
+    order.extend(
+        (0..tasks.len())
+            .filter(|&index| !visited[index])
+            .map(|index| (0, index)),
+    );
+
+    let mut tasks: Vec<Option<TodoView>> = tasks.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|(depth, index)| tasks[index].take().map(|task| (depth, task)))
+        .collect()
+}
+
+/// Formats a task timestamp to the minute, empty when unknown
+fn timestamp(time: Option<NaiveDateTime>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Whether a pending task is waiting on prerequisites
+const fn is_blocked(task: &TodoView) -> bool {
+    !task.complete && !task.blocked_by.is_empty()
+}
+
+/// Completion of a task's subtasks e.g. ` (2/5 done)`, empty without subtasks
+fn subtask_progress(task: &TodoView) -> String {
+    if task.subtasks_total == 0 {
+        String::new()
+    } else {
+        format!(" ({}/{} done)", task.subtasks_done, task.subtasks_total)
+    }
+}
+
+/// Colour coding for the priority column
+const fn priority_style(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Urgent => "bFm",
+        Priority::High => "Fy",
+        Priority::Medium => "",
+        Priority::Low => "Fg",
+    }
+}
+
+/// Shows every field of a task as a two column table, leaving out those without a value
+#[must_use]
+pub fn show_task(task: &TodoView) -> Table {
+    let ids = |ids: &[u64]| {
+        ids.iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let fields = [
+        ("ID", task.id.to_string()),
+        ("PROJECT", task.project.clone()),
+        ("TASK", task.task.clone()),
+        ("TAGS", task.tags.join(", ")),
+        ("PRIORITY", task.priority.to_string()),
+        (
+            "DUE DATE",
+            task.due_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+        ),
+        ("COMPLETE", task.complete.to_string()),
+        (
+            "PARENT",
+            task.parent_id.map(|id| id.to_string()).unwrap_or_default(),
+        ),
+        (
+            "SUBTASKS",
+            if task.subtasks_total == 0 {
+                String::new()
+            } else {
+                format!("{}/{} done", task.subtasks_done, task.subtasks_total)
+            },
+        ),
+        ("BLOCKED BY", ids(&task.blocked_by)),
+        ("CREATED", timestamp(task.created_at)),
+        ("UPDATED", timestamp(task.updated_at)),
+        ("COMPLETED", timestamp(task.completed_at)),
+        ("ARCHIVED AT", timestamp(task.archived_at)),
+        (
+            "NOTES",
+            task.notes
+                .as_deref()
+                .unwrap_or_default()
+                .trim_end()
+                .to_owned(),
+        ),
+    ];
+
+    let mut table = Table::new();
+    for (name, value) in fields {
+        if value.is_empty() {
+            continue;
+        }
+        let style = if name == "PRIORITY" {
+            priority_style(task.priority)
         } else {
-            eprintln!(
-                "Date on task '{}' is incorrect, delete and update for correct overdue marking",
-                row.id
-            );
-            NaiveDate::parse_from_str("2000-01-01", "%Y-%m-%d").unwrap()
+            ""
         };
-        let naive_time = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
-        let naive_date_time = NaiveDateTime::new(naive_date, naive_time);
-        let utc_date_time = DateTime::<Utc>::from_naive_utc_and_offset(naive_date_time, Utc);
+        table.add_row(Row::new(vec![
+            Cell::new(name).style_spec("b"),
+            Cell::new(&value).style_spec(style),
+        ]));
+    }
 
-        if today > utc_date_time && !row.complete {
-            table.add_row(row![
+    table
+}
+
+/// Shows task data in a formatted table, `timestamps` adds created, updated and completed
+/// columns
+///
+/// Listings with archived tasks get a column for when they were archived.
+#[must_use]
+pub fn show_data(data: Vec<TodoView>, timestamps: bool) -> Table {
+    let today = Local::now().date_naive();
+    let archived = data.iter().any(|task| task.archived);
+    let mut table = Table::new();
+    let mut header = row!["ID", "PROJECT", "TASK", "TAGS", "PRIORITY", "DUE DATE", "COMPLETE"];
+    if archived {
+        header.add_cell(Cell::new("ARCHIVED AT"));
+    }
+    if timestamps {
+        for title in ["CREATED", "UPDATED", "COMPLETED"] {
+            header.add_cell(Cell::new(title));
+        }
+    }
+    table.add_row(header);
+
+    for (depth, row) in task_tree(data) {
+        let due_date = row
+            .due_date
+            .map_or_else(String::new, |date| date.to_string());
+        let tags = row.tags.join(", ");
+        let branch = if depth == 0 {
+            String::new()
+        } else {
+            format!("{}└ ", "  ".repeat(depth - 1))
+        };
+        let task = format!("{branch}{}{}", row.task, subtask_progress(&row));
+        let complete = if is_blocked(&row) {
+            String::from("BLOCKED")
+        } else {
+            row.complete.to_string()
+        };
+
+        let overdue = row.due_date.is_some_and(|date| date < today) && !row.complete;
+        let mut table_row = if overdue {
+            row![
                 bFr => row.id,
                 row.project,
-                row.task,
-                row.due_date,
-                row.complete
-            ]);
+                task,
+                tags,
+                row.priority,
+                due_date,
+                complete
+            ]
         } else {
-            table.add_row(row![
-                row.id,
-                row.project,
-                row.task,
-                row.due_date,
-                row.complete
-            ]);
+            Row::new(vec![
+                Cell::new(&row.id.to_string()),
+                Cell::new(&row.project),
+                Cell::new(&task),
+                Cell::new(&tags),
+                Cell::new(&row.priority.to_string()).style_spec(priority_style(row.priority)),
+                Cell::new(&due_date),
+                Cell::new(&complete),
+            ])
+        };
+
+        let mut times = Vec::new();
+        if archived {
+            times.push(row.archived_at);
         }
+        if timestamps {
+            times.extend([row.created_at, row.updated_at, row.completed_at]);
+        }
+        for time in times {
+            let cell = Cell::new(&timestamp(time));
+            table_row.add_cell(if overdue {
+                cell.style_spec("bFr")
+            } else {
+                cell
+            });
+        }
+
+        table.add_row(table_row);
     }
 
     // return the table
@@ -56,7 +769,8 @@ pub fn show_data(data: Vec<TodoView>) -> Table {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::TodoView;
+    use crate::database::{history::EventAction, Priority, TodoView};
+    use chrono::NaiveDate;
 
     #[test]
     fn test_output() {
@@ -64,16 +778,287 @@ mod tests {
             id: 1,
             project: String::from("Apple"),
             task: String::from("Test"),
-            due_date: String::from("2023-01-01"),
+            due_date: NaiveDate::from_ymd_opt(2023, 1, 1),
+            complete: false,
+            priority: Priority::High,
+            tags: vec![String::from("fruit"), String::from("shopping")],
+            ..TodoView::default()
+        }];
+
+        assert_eq!(
+            table!(
+                ["ID", "PROJECT", "TASK", "TAGS", "PRIORITY", "DUE DATE", "COMPLETE"],
+                [bFr => 1, "Apple", "Test", "fruit, shopping", "high", "2023-01-01", false]
+            ),
+            show_data(test_data, false)
+        );
+    }
+
+    #[test]
+    fn priority_is_colour_coded() {
+        let test_data = vec![TodoView {
+            id: 2,
+            project: String::from("Apple"),
+            task: String::from("Later"),
+            due_date: NaiveDate::from_ymd_opt(9999, 1, 1),
             complete: false,
+            priority: Priority::Urgent,
+            tags: Vec::new(),
+            ..TodoView::default()
         }];
 
         assert_eq!(
             table!(
-                ["ID", "PROJECT", "TASK", "DUE DATE", "COMPLETE"],
-                [bFr => 1, "Apple", "Test", "2023-01-01", false]
+                ["ID", "PROJECT", "TASK", "TAGS", "PRIORITY", "DUE DATE", "COMPLETE"],
+                [2, "Apple", "Later", "", bFm -> "urgent", "9999-01-01", false]
             ),
-            show_data(test_data)
+            show_data(test_data, false)
+        );
+    }
+
+    fn sample() -> TodoView {
+        TodoView {
+            id: 3,
+            project: String::from("Home"),
+            task: String::from("Say \"hi\", then leave"),
+            due_date: NaiveDate::from_ymd_opt(2024, 6, 14),
+            complete: true,
+            priority: Priority::Low,
+            tags: vec![String::from("a"), String::from("b")],
+            ..TodoView::default()
+        }
+    }
+
+    fn stamped() -> TodoView {
+        let at = |value: &str| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").ok();
+        TodoView {
+            created_at: at("2024-06-10 08:30"),
+            updated_at: at("2024-06-12 17:05"),
+            completed_at: at("2024-06-12 17:05"),
+            notes: Some(String::from("Ring first\nKey is under the mat")),
+            ..sample()
+        }
+    }
+
+    #[test]
+    fn timestamps_are_optional_table_columns() {
+        let table = show_data(vec![stamped()], false);
+        assert_eq!(7, table.get_row(0).unwrap().len());
+
+        let table = show_data(vec![stamped()], true);
+        let header = table.get_row(0).unwrap();
+        assert_eq!("CREATED", header.get_cell(7).unwrap().get_content());
+        assert_eq!("COMPLETED", header.get_cell(9).unwrap().get_content());
+        assert_eq!(
+            "2024-06-10 08:30",
+            table.get_row(1).unwrap().get_cell(7).unwrap().get_content()
+        );
+    }
+
+    #[test]
+    fn archived_listings_show_when_tasks_were_archived() {
+        assert_eq!(
+            7,
+            show_data(vec![sample()], false).get_row(0).unwrap().len()
+        );
+
+        let archived = TodoView {
+            archived: true,
+            archived_at: "2024-06-20T09:15:00".parse().ok(),
+            ..stamped()
+        };
+        let table = show_data(vec![archived], true);
+        let header = table.get_row(0).unwrap();
+        assert_eq!("ARCHIVED AT", header.get_cell(7).unwrap().get_content());
+        assert_eq!("CREATED", header.get_cell(8).unwrap().get_content());
+        assert_eq!(
+            "2024-06-20 09:15",
+            table.get_row(1).unwrap().get_cell(7).unwrap().get_content()
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let json: Value = serde_json::from_str(&tasks_to_json(&[sample()])).unwrap();
+
+        assert_eq!(
+            serde_json::json!([{
+                "id": 3,
+                "project": "Home",
+                "task": "Say \"hi\", then leave",
+                "due_date": "2024-06-14",
+                "complete": true,
+                "priority": "low",
+                "tags": ["a", "b"],
+                "archived": false,
+                "parent_id": null,
+                "archived_at": null,
+                "subtasks_total": 0,
+                "subtasks_done": 0,
+                "blocked_by": [],
+                "created_at": null,
+                "updated_at": null,
+                "completed_at": null,
+                "notes": null,
+            }]),
+            json
+        );
+        let stats: Value = serde_json::from_str(&stats_to_json(&[
+            ("pending", StatValue::Count(2)),
+            ("avg_days_to_complete", StatValue::Days(Some(1.25))),
+            ("overdue", StatValue::Days(None)),
+        ]))
+        .unwrap();
+        assert_eq!(
+            serde_json::json!({"pending": 2, "avg_days_to_complete": 1.3, "overdue": null}),
+            stats
+        );
+    }
+
+    #[test]
+    fn csv_and_tsv_output() {
+        assert_eq!(
+            "id,project,task,tags,priority,due_date,complete,archived,archived_at,parent_id,subtasks_done,subtasks_total,blocked_by,created_at,updated_at,completed_at,notes\n\
+             3,Home,\"Say \"\"hi\"\", then leave\",\"a,b\",low,2024-06-14,true,false,,,0,0,,2024-06-10 08:30,2024-06-12 17:05,2024-06-12 17:05,\"Ring first\nKey is under the mat\"\n",
+            tasks_to_delimited(&[stamped()], ',')
+        );
+        assert_eq!(
+            "id\tproject\ttask\ttags\tpriority\tdue_date\tcomplete\tarchived\tarchived_at\tparent_id\tsubtasks_done\tsubtasks_total\tblocked_by\tcreated_at\tupdated_at\tcompleted_at\tnotes\n\
+             3\tHome\tSay \"hi\", then leave\ta,b\tlow\t2024-06-14\ttrue\tfalse\t\t\t0\t0\t\t\t\t\t\n",
+            tasks_to_delimited(&[sample()], '\t')
+        );
+    }
+
+    #[test]
+    fn task_details_include_notes_and_skip_empty_fields() {
+        let table = show_task(&stamped());
+        let fields: Vec<(String, String)> = table
+            .row_iter()
+            .map(|row| {
+                let cell = |index| row.get_cell(index).unwrap().get_content();
+                (cell(0), cell(1))
+            })
+            .collect();
+
+        assert_eq!(("ID".to_owned(), "3".to_owned()), fields[0]);
+        assert!(!fields.iter().any(|(name, _)| name == "PARENT"));
+        assert_eq!(
+            Some(&(
+                "NOTES".to_owned(),
+                "Ring first\nKey is under the mat".to_owned()
+            )),
+            fields.last()
+        );
+    }
+
+    #[test]
+    fn plain_output() {
+        assert_eq!(
+            "[x] 3 Say \"hi\", then leave (due 2024-06-14) +a +b",
+            task_to_plain(&sample())
+        );
+
+        let blocked = TodoView {
+            complete: false,
+            blocked_by: vec![1],
+            ..sample()
+        };
+        assert_eq!(
+            "[ ] 3 Say \"hi\", then leave (due 2024-06-14) +a +b (blocked)",
+            task_to_plain(&blocked)
+        );
+    }
+
+    #[test]
+    fn event_changes_describe_the_event() {
+        let state = |value: Value| match value {
+            Value::Object(state) => Some(state),
+            _ => None,
+        };
+        let event = |action, old_values, new_values| TaskEvent {
+            id: 1,
+            task_id: 3,
+            task: String::from("Buy milk"),
+            action,
+            old_values,
+            new_values,
+            at: NaiveDateTime::default(),
+        };
+
+        let edit = event(
+            EventAction::Edit,
+            state(serde_json::json!({"due_date": "2024-06-14", "tags": []})),
+            state(serde_json::json!({"due_date": "2024-06-21", "tags": ["a", "b"]})),
+        );
+        assert_eq!(
+            "due_date: 2024-06-14 -> 2024-06-21, tags: none -> a,b",
+            event_changes(&edit)
+        );
+
+        let note = event(
+            EventAction::Edit,
+            state(serde_json::json!({"notes": null})),
+            state(serde_json::json!({"notes": "Semi-skimmed\nTwo pints"})),
+        );
+        assert_eq!("notes added", event_changes(&note));
+
+        let delete = event(
+            EventAction::Delete,
+            state(serde_json::json!({
+                "task": "Buy milk", "project": "Home", "parent_id": null, "notes": "Semi-skimmed"
+            })),
+            None,
+        );
+        assert_eq!("parent_id: none, project: Home", event_changes(&delete));
+    }
+
+    #[test]
+    fn subtasks_are_shown_as_a_tree() {
+        let task = |id, parent_id, name: &str| TodoView {
+            id,
+            parent_id,
+            task: String::from(name),
+            due_date: NaiveDate::from_ymd_opt(9999, 1, 1),
+            ..TodoView::default()
+        };
+        let parent = TodoView {
+            subtasks_done: 1,
+            subtasks_total: 2,
+            ..task(1, None, "Plan trip")
+        };
+
+        let tasks = vec![
+            task(3, Some(1), "Book hotel"),
+            task(4, Some(3), "Pick area"),
+            parent,
+            task(2, Some(1), "Book flights"),
+            task(5, Some(42), "Parent not listed"),
+        ];
+
+        let tree: Vec<(usize, u64)> = task_tree(tasks)
+            .iter()
+            .map(|(depth, task)| (*depth, task.id))
+            .collect();
+        assert_eq!(vec![(0, 1), (1, 3), (2, 4), (1, 2), (0, 5)], tree);
+
+        let table = show_data(
+            vec![
+                TodoView {
+                    subtasks_done: 1,
+                    subtasks_total: 2,
+                    ..task(1, None, "Plan trip")
+                },
+                task(2, Some(1), "Book flights"),
+            ],
+            false,
+        );
+        assert_eq!(
+            "Plan trip (1/2 done)",
+            table.get_row(1).unwrap().get_cell(2).unwrap().get_content()
+        );
+        assert_eq!(
+            "└ Book flights",
+            table.get_row(2).unwrap().get_cell(2).unwrap().get_content()
         );
     }
 }